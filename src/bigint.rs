@@ -0,0 +1,166 @@
+//! Implements the traits needed to run Fractran programs over arbitrary-size
+//! integers from `num_bigint`, which never overflow.
+
+use core::convert::TryFrom;
+
+use num_bigint::BigUint;
+
+use super::frac::FractranNat;
+use super::primebasis::{primes_for, Divides, PrimeBasis};
+
+impl Divides for BigUint {
+    fn divides(&self, rhs: &Self) -> bool {
+        (rhs % self).bits() == 0
+    }
+}
+
+impl FractranNat for BigUint {
+    fn to_u64_checked(&self) -> Option<u64> {
+        u64::try_from(self).ok()
+    }
+
+    fn is_zero(&self) -> bool {
+        self.bits() == 0
+    }
+
+    fn is_one(&self) -> bool {
+        self.bits() == 1
+    }
+
+    fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+        Some(self * rhs)
+    }
+
+    fn gcd(&self, other: &Self) -> Self {
+        let (mut a, mut b) = (self.clone(), other.clone());
+        while b.bits() != 0 {
+            let r = &a % &b;
+            a = b;
+            b = r;
+        }
+        a
+    }
+
+    fn is_power_of(&self, prime: u64) -> Option<u32> {
+        if prime < 2 || FractranNat::is_zero(self) {
+            return None;
+        }
+        let (mut curr, mut exp) = (self.clone(), 0);
+        while (&curr % prime).bits() == 0 {
+            curr /= prime;
+            exp += 1;
+        }
+        if FractranNat::is_one(&curr) {
+            Some(exp)
+        } else {
+            None
+        }
+    }
+}
+
+impl PrimeBasis {
+    /// Returns the exact number this prime basis represents as a `BigUint`,
+    /// which never overflows no matter how large the exponents are. This is
+    /// only needed to read off a large state: execution is still cheaper in
+    /// the factored form.
+    pub fn to_biguint(&self) -> BigUint {
+        self.exps
+            .iter()
+            .zip(primes_for(&self.exps).iter())
+            .fold(BigUint::from(1_u64), |acc, (&exp, &p)| {
+                acc * BigUint::from(p).pow(exp)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frac::Fraction;
+    use crate::program::Program;
+
+    #[test]
+    fn test_divides() {
+        let big = |n: u64| BigUint::from(n);
+        assert!(big(7).divides(&big(28)));
+        assert!(big(1).divides(&big(1)));
+        assert!(!big(2).divides(&big(7)));
+        assert!(!big(100).divides(&big(250)));
+    }
+
+    #[test]
+    fn test_is_power_of() {
+        let big = |n: u64| BigUint::from(n);
+        assert_eq!(big(1).is_power_of(2), Some(0));
+        assert_eq!(big(243).is_power_of(3), Some(5));
+        assert_eq!(big(12).is_power_of(2), None);
+        assert_eq!((big(1) << 100_usize).is_power_of(2), Some(100));
+    }
+
+    #[test]
+    fn test_gcd() {
+        let big = |n: u64| BigUint::from(n);
+        assert_eq!(FractranNat::gcd(&big(12), &big(18)), big(6));
+        assert_eq!(FractranNat::gcd(&big(7), &big(1)), big(1));
+        let huge = BigUint::from(3_u64) << 200_usize;
+        assert_eq!(FractranNat::gcd(&huge, &big(10)), big(2));
+    }
+
+    #[test]
+    fn test_to_biguint() {
+        let pb = |n: u64| PrimeBasis::try_new(n).unwrap();
+        assert_eq!(pb(1).to_biguint(), BigUint::from(1_u64));
+        assert_eq!(pb(200).to_biguint(), BigUint::from(200_u64));
+        // 2^100 ✕ 7^3 is far too big for a u64
+        let huge = PrimeBasis::from(vec![100, 0, 0, 3]);
+        assert_eq!(
+            huge.to_biguint(),
+            (BigUint::from(1_u64) << 100_usize) * 343_u32
+        );
+        assert_eq!(
+            huge.to_biguint().to_string(),
+            "434804155878282684713369199443968"
+        );
+
+        // registers past the global table are still included
+        let table = crate::math::PrimeTable::new(1100);
+        let past = PrimeBasis::try_new_in(table[1050], &table).unwrap();
+        assert_eq!(past.to_biguint(), BigUint::from(table[1050]));
+    }
+
+    #[test]
+    fn test_readme_primes_biguint() {
+        let nums: Vec<u64> = vec![17, 78, 19, 23, 29, 77, 95, 77, 1, 11, 13, 15, 15, 55];
+        let denoms: Vec<u64> = vec![91, 85, 51, 38, 33, 29, 23, 19, 17, 13, 11, 14, 2, 1];
+        let fracs: Vec<Fraction<BigUint>> = nums
+            .into_iter()
+            .zip(denoms)
+            .map(|(num, denom)| Fraction::new(BigUint::from(num), BigUint::from(denom)))
+            .collect();
+
+        // u64 overflows long before this produces 11
+        let prog = Program::new(fracs);
+        let primes: Vec<u32> = prog
+            .lazy_exec(BigUint::from(2_u64))
+            .take(20_000)
+            .filter_map(|out| out.is_power_of(2))
+            .collect();
+        assert_eq!(primes, vec![2, 3, 5, 7, 11, 13, 17, 19, 23]);
+    }
+
+    #[test]
+    fn test_convert() {
+        let small = Program::new(vec![Fraction::new(3_u64, 2), Fraction::new(1, 5)]);
+        let big: Program<BigUint> = small.clone().convert().unwrap();
+        assert_eq!(big.clone().convert::<u64>(), Ok(small));
+
+        let huge = Program::new(vec![Fraction::new(
+            BigUint::from(1_u64) << 70_usize,
+            3_u64.into(),
+        )]);
+        assert_eq!(
+            huge.convert::<u64>(),
+            Err(crate::program::ConvertError::TooLarge { fraction: 0 })
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Provides an extension of Fractran, sometimes called FRACTRAN++, where a
+//! program can also halt or jump explicitly instead of only applying the first
+//! fraction that gives an integer.
+
+use super::frac::{Fraction, FractranNat, StepResult};
+use super::program::{Program, ProgramError};
+use alloc::vec::Vec;
+use core::iter::FusedIterator;
+
+/// A single instruction in an extended program.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Instruction<T: FractranNat> {
+    /// Multiplies the state by the fraction if the product is an integer and
+    /// then goes back to the first instruction, just like normal Fractran.
+    /// Otherwise, moves on to the next instruction.
+    Frac(Fraction<T>),
+    /// Stops the program immediately.
+    Halt,
+    /// Goes to the instruction at index `target` if the state is a multiple of
+    /// `factor`, and moves on to the next instruction otherwise.
+    JumpIfMultiple { factor: T, target: usize },
+}
+
+/// A program in extended Fractran: a list of instructions executed in order,
+/// keeping track of a program counter. Running off the end of the list halts
+/// the program, so a list of only `Frac` instructions behaves exactly like the
+/// corresponding `Program`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ExtendedProgram<T: FractranNat> {
+    /// The list of instructions that comprises the program.
+    instructions: Vec<Instruction<T>>,
+}
+
+impl<T: FractranNat> ExtendedProgram<T> {
+    /// Attempts to make a new `ExtendedProgram` with the given list of
+    /// instructions. Returns `EmptyProgram` if the list is empty and
+    /// `InvalidJumpTarget` if any jump goes past the end of the list.
+    pub fn try_new(instructions: Vec<Instruction<T>>) -> Result<ExtendedProgram<T>, ProgramError> {
+        if instructions.is_empty() {
+            return Err(ProgramError::EmptyProgram);
+        }
+        for (i, instruction) in instructions.iter().enumerate() {
+            if let Instruction::JumpIfMultiple { target, .. } = instruction {
+                if *target >= instructions.len() {
+                    return Err(ProgramError::InvalidJumpTarget {
+                        instruction: i,
+                        target: *target,
+                    });
+                }
+            }
+        }
+        Ok(ExtendedProgram { instructions })
+    }
+
+    /// Makes a new `ExtendedProgram` with the given list of instructions,
+    /// panicking if it's empty or has a jump past the end of the list.
+    pub fn new(instructions: Vec<Instruction<T>>) -> ExtendedProgram<T> {
+        match ExtendedProgram::try_new(instructions) {
+            Ok(prog) => prog,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Returns the instructions that make up this program, in order.
+    pub fn instructions(&self) -> &[Instruction<T>] {
+        &self.instructions
+    }
+
+    /// Returns an iterator that lazily executes the program using a single
+    /// input, yielding the state each time a fraction is applied and stopping
+    /// when the program halts. Note that a program that jumps in a loop without
+    /// ever applying a fraction will make `next()` run forever.
+    pub fn lazy_exec(self, input: T) -> impl FusedIterator<Item = T> {
+        ExtendedEvaluator {
+            instructions: self.instructions,
+            curr_state: input,
+            finished: false,
+        }
+    }
+
+    /// Returns the final state of the program, or the input if it halts
+    /// without applying any fractions: this will obviously never terminate if
+    /// the program itself doesn't.
+    pub fn exec_to_completion(self, input: T) -> T {
+        let mut evaluator = ExtendedEvaluator {
+            instructions: self.instructions,
+            curr_state: input,
+            finished: false,
+        };
+        while evaluator.next().is_some() {}
+        evaluator.curr_state
+    }
+}
+
+impl<T: FractranNat> From<Program<T>> for ExtendedProgram<T> {
+    /// Converts a normal program into the equivalent extended program, which
+    /// has one `Frac` instruction for each fraction.
+    fn from(prog: Program<T>) -> Self {
+        ExtendedProgram {
+            instructions: prog
+                .fractions()
+                .iter()
+                .cloned()
+                .map(Instruction::Frac)
+                .collect(),
+        }
+    }
+}
+
+/// An iterator that holds the state of an extended program as it runs and,
+/// each time `next()` is called, continues until the next fraction applies.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct ExtendedEvaluator<T: FractranNat> {
+    /// The program being run as a list of instructions.
+    instructions: Vec<Instruction<T>>,
+
+    /// The current state of the program.
+    curr_state: T,
+
+    /// Whether this program is over.
+    finished: bool,
+}
+
+impl<T: FractranNat> Iterator for ExtendedEvaluator<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let mut pc = 0;
+        while let Some(instruction) = self.instructions.get(pc) {
+            match instruction {
+                Instruction::Frac(frac) => {
+                    if let StepResult::Changed(new_state) = frac.exec(self.curr_state.clone()) {
+                        self.curr_state = new_state;
+                        return Some(self.curr_state.clone());
+                    }
+                    pc += 1;
+                }
+                Instruction::Halt => break,
+                Instruction::JumpIfMultiple { factor, target } => {
+                    if factor.divides(&self.curr_state) {
+                        pc = *target;
+                    } else {
+                        pc += 1;
+                    }
+                }
+            }
+        }
+        // either halted explicitly or ran off the end
+        self.finished = true;
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.finished {
+            (0, Some(0))
+        } else {
+            (0, None)
+        }
+    }
+}
+
+impl<T: FractranNat> FusedIterator for ExtendedEvaluator<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Halves the input until it's odd, unless it starts as a multiple of 3.
+    fn halve_unless_triple() -> ExtendedProgram<u64> {
+        ExtendedProgram::new(vec![
+            Instruction::JumpIfMultiple {
+                factor: 3,
+                target: 2,
+            },
+            Instruction::Frac(Fraction::new(1, 2)),
+            Instruction::Halt,
+        ])
+    }
+
+    #[test]
+    fn test_jump_and_halt() {
+        let steps: Vec<u64> = halve_unless_triple().lazy_exec(8).collect();
+        assert_eq!(steps, vec![4, 2, 1]);
+        assert_eq!(halve_unless_triple().lazy_exec(24).next(), None);
+        assert_eq!(halve_unless_triple().exec_to_completion(24), 24);
+        assert_eq!(halve_unless_triple().exec_to_completion(40), 5);
+    }
+
+    #[test]
+    fn test_from_program() {
+        let mult = Program::new(
+            vec![(455, 33), (11, 13), (1, 11), (3, 7), (11, 2), (1, 3)]
+                .into_iter()
+                .map(|(num, denom)| Fraction::new(num, denom))
+                .collect(),
+        );
+        let extended = ExtendedProgram::from(mult.clone());
+        assert_eq!(
+            extended.lazy_exec(72_u64).collect::<Vec<_>>(),
+            mult.lazy_exec(72_u64).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_invalid_programs() {
+        assert_eq!(
+            ExtendedProgram::<u64>::try_new(vec![]),
+            Err(ProgramError::EmptyProgram)
+        );
+        assert_eq!(
+            ExtendedProgram::try_new(vec![
+                Instruction::Halt,
+                Instruction::JumpIfMultiple {
+                    factor: 2_u64,
+                    target: 2,
+                },
+            ]),
+            Err(ProgramError::InvalidJumpTarget {
+                instruction: 1,
+                target: 2,
+            })
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! Provides a version of `PrimeBasis` with a fixed number of registers, stored
+//! inline instead of on the heap. Programs that only ever touch a handful of
+//! primes can use this to avoid allocating a new state on every step.
+
+use core::convert::TryFrom;
+use core::ops::{Div, Mul};
+
+use super::frac::FractranNat;
+use super::primebasis::{
+    checked_value_of_exps, godel_string, power_of_prime_in_exps, primes_for, Divides, Error,
+    PrimeBasis,
+};
+
+/// A natural number whose prime factors are among the first `N` primes,
+/// represented as the array of exponents [a, b, c, ...] = 2^a * 3^b * 5^c *
+/// ... like `PrimeBasis`. Because the array has a fixed size, this is `Copy`
+/// and never allocates, but it can only represent numbers using its `N`
+/// registers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FixedBasis<const N: usize> {
+    /// The exponent of each of the first `N` primes.
+    pub exps: [u32; N],
+}
+
+impl<const N: usize> FixedBasis<N> {
+    /// Attempts to create the fixed basis representation of the given natural
+    /// number. Returns `RegisterOverflow` if the number has a prime factor
+    /// past the first `N` primes, and `NumIsZero` if the number given is zero.
+    pub fn try_new(num: u64) -> Result<FixedBasis<N>, Error> {
+        let pb = PrimeBasis::try_new(num)?;
+        FixedBasis::from_prime_basis(&pb).ok_or(Error::RegisterOverflow(num))
+    }
+
+    /// Copies the exponents of `pb`, or returns `None` if it uses a register
+    /// past the first `N`.
+    pub fn from_prime_basis(pb: &PrimeBasis) -> Option<FixedBasis<N>> {
+        if pb.exps.iter().skip(N).any(|&exp| exp != 0) {
+            return None;
+        }
+        let mut exps = [0; N];
+        for (fixed, &exp) in exps.iter_mut().zip(&pb.exps) {
+            *fixed = exp;
+        }
+        Some(FixedBasis { exps })
+    }
+
+    /// Returns the exponent of the `i`th prime, thinking of it as the value of
+    /// the `i`th register. Registers past `N` are 0.
+    pub fn register(&self, i: usize) -> u32 {
+        self.exps.get(i).copied().unwrap_or(0)
+    }
+
+    /// Returns the number corresponding to this basis. Overflows if the number
+    /// doesn't fit in a `u64`: use `to_u64_checked` to check for that.
+    pub fn value(&self) -> u64 {
+        self.exps
+            .iter()
+            .zip(primes_for(&self.exps).iter())
+            .fold(1, |acc, (&exp, p)| acc * p.pow(exp))
+    }
+
+    /// Applies `op` to each pair of exponents, returning `None` if it ever does.
+    fn zip_exps(&self, rhs: &Self, op: impl Fn(u32, u32) -> Option<u32>) -> Option<Self> {
+        let mut exps = [0; N];
+        for (i, exp) in exps.iter_mut().enumerate() {
+            *exp = op(self.exps[i], rhs.exps[i])?;
+        }
+        Some(FixedBasis { exps })
+    }
+}
+
+impl<const N: usize> Default for FixedBasis<N> {
+    /// Returns 1, which has every register at 0.
+    fn default() -> Self {
+        FixedBasis { exps: [0; N] }
+    }
+}
+
+impl<const N: usize> core::fmt::Display for FixedBasis<N> {
+    /// Writes the factored form, like `FixedBasis(2^3 ✕ 5^2)`. With the
+    /// alternate flag (`{:#}`), writes the decimal value instead if it fits in
+    /// a `u64`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if f.alternate() {
+            if let Some(value) = self.to_u64_checked() {
+                return write!(f, "{}", value);
+            }
+        }
+        match godel_string(&self.exps) {
+            godel_str if godel_str.is_empty() => write!(f, "FixedBasis(1)"),
+            godel_str => write!(f, "FixedBasis({})", godel_str),
+        }
+    }
+}
+
+impl<const N: usize> Mul for FixedBasis<N> {
+    type Output = FixedBasis<N>;
+
+    /// Returns the product of the numbers the inputs represent, by adding
+    /// their exponents.
+    fn mul(self, rhs: Self) -> Self::Output {
+        match self.zip_exps(&rhs, u32::checked_add) {
+            Some(product) => product,
+            None => panic!("Exponent overflow multiplying {} by {}", self, rhs),
+        }
+    }
+}
+
+impl<const N: usize> Div for FixedBasis<N> {
+    type Output = FixedBasis<N>;
+
+    /// Returns the quotient of the numbers the inputs represent, by
+    /// subtracting their exponents. Panics if the output would not be a
+    /// natural number.
+    fn div(self, rhs: Self) -> Self::Output {
+        match self.zip_exps(&rhs, u32::checked_sub) {
+            Some(quotient) => quotient,
+            None => panic!("Can't divide {} by {}", self, rhs),
+        }
+    }
+}
+
+impl<const N: usize> Divides for FixedBasis<N> {
+    /// Checks if `rhs` is a multiple of `self`.
+    fn divides(&self, rhs: &Self) -> bool {
+        self.exps.iter().zip(&rhs.exps).all(|(a, b)| a <= b)
+    }
+}
+
+impl<const N: usize> FractranNat for FixedBasis<N> {
+    fn to_u64_checked(&self) -> Option<u64> {
+        checked_value_of_exps(&self.exps)
+    }
+
+    fn is_zero(&self) -> bool {
+        // zero has no prime factorization, so it can't be represented
+        false
+    }
+
+    fn is_one(&self) -> bool {
+        self.exps.iter().all(|&exp| exp == 0)
+    }
+
+    fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+        self.zip_exps(rhs, u32::checked_add)
+    }
+
+    fn divides_product(&self, lhs: &Self, rhs: &Self) -> bool {
+        (0..N).all(|i| self.exps[i] as u64 <= lhs.exps[i] as u64 + rhs.exps[i] as u64)
+    }
+
+    fn gcd(&self, other: &Self) -> Self {
+        let mut exps = self.exps;
+        for (exp, &other_exp) in exps.iter_mut().zip(&other.exps) {
+            *exp = (*exp).min(other_exp);
+        }
+        FixedBasis { exps }
+    }
+
+    fn is_power_of(&self, prime: u64) -> Option<u32> {
+        let primes = primes_for(&self.exps);
+        match primes[..N.min(primes.len())].binary_search(&prime) {
+            Ok(index) => power_of_prime_in_exps(&self.exps, index),
+            // not a prime we can store, so only 1 is a power of it
+            Err(_) if prime >= 2 && self.is_one() => Some(0),
+            Err(_) => None,
+        }
+    }
+}
+
+impl<const N: usize> TryFrom<u64> for FixedBasis<N> {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        Self::try_new(value)
+    }
+}
+
+impl<const N: usize> From<[u32; N]> for FixedBasis<N> {
+    /// Uses the given exponents directly, so `[3, 0, 2]` is 2^3 ✕ 5^2.
+    fn from(exps: [u32; N]) -> FixedBasis<N> {
+        FixedBasis { exps }
+    }
+}
+
+impl<const N: usize> From<FixedBasis<N>> for PrimeBasis {
+    /// Copies the exponents into a `PrimeBasis`, which can represent anything a
+    /// `FixedBasis` can.
+    fn from(fixed: FixedBasis<N>) -> PrimeBasis {
+        PrimeBasis::from(&fixed.exps[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+
+    /// Constructor that panics instead of returning a result
+    fn new(num: u64) -> FixedBasis<4> {
+        FixedBasis::try_new(num).unwrap()
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(new(200).exps, [3, 0, 2, 0]);
+        assert_eq!(new(1), FixedBasis::default());
+        assert_eq!(new(200).value(), 200);
+        assert_eq!(FixedBasis::<4>::try_new(0), Err(Error::NumIsZero));
+        // 11 is the fifth prime
+        assert_eq!(
+            FixedBasis::<4>::try_new(22),
+            Err(Error::RegisterOverflow(22))
+        );
+
+        let pb = PrimeBasis::try_new(200).unwrap();
+        assert_eq!(FixedBasis::from_prime_basis(&pb), Some(new(200)));
+        assert_eq!(PrimeBasis::from(new(200)), pb);
+        assert_eq!(FixedBasis::<2>::from_prime_basis(&pb), None);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(new(12) * new(35), new(420));
+        assert_eq!(new(420) / new(35), new(12));
+        assert!(new(6).divides(&new(12)));
+        assert!(!new(4).divides(&new(6)));
+        assert!(new(4).divides_product(&new(2), &new(6)));
+        assert_eq!(FractranNat::gcd(&new(12), &new(90)), new(6));
+        assert_eq!(new(8).is_power_of(2), Some(3));
+        assert_eq!(new(1).is_power_of(11), Some(0));
+        assert_eq!(new(6).is_power_of(2), None);
+
+        let big = FixedBasis::from([u32::MAX, 0, 0, 0]);
+        assert_eq!(big.checked_mul(&new(2)), None);
+        assert_eq!(big.to_u64_checked(), None);
+        assert_eq!(format!("{}", new(200)), "FixedBasis(2^3 ✕ 5^2)");
+        assert_eq!(format!("{:#}", new(200)), "200");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_bad_div() {
+        let _ = new(6) / new(4);
+    }
+
+    #[test]
+    fn test_matches_prime_basis() {
+        let fracs = [(455, 33), (11, 13), (1, 11), (3, 7), (11, 2), (1, 3)];
+        let fixed: Program<FixedBasis<6>> = Program::new(
+            fracs
+                .iter()
+                .map(|&(num, denom)| {
+                    crate::frac::Fraction::new(
+                        FixedBasis::try_new(num).unwrap(),
+                        FixedBasis::try_new(denom).unwrap(),
+                    )
+                })
+                .collect(),
+        );
+        let dynamic = Program::from_u64_fractions(&fracs).unwrap();
+        let input = FixedBasis::from([5, 7, 0, 0, 0, 0]);
+        let out = fixed.exec_to_completion(input);
+        assert_eq!(out.register(2), 35);
+        assert_eq!(
+            PrimeBasis::from(out),
+            dynamic.exec_to_completion(PrimeBasis::from(input))
+        );
+    }
+}
@@ -1,33 +1,118 @@
 //! A representation of a fraction used in Fractran program execution.
 
 use super::primebasis::Divides;
-use std::fmt;
-use std::ops::{Div, Mul};
+use core::convert::TryFrom;
+use core::fmt;
+use core::ops::{Div, Mul};
+
+use thiserror::Error;
 
 /// Wrapper trait for the various things that numbers in Fractran programs need
-/// to do. `PrimeBasis` satisfies this, as does `u64`.
+/// to do. `PrimeBasis` and `FixedBasis` satisfy this, as do `u64` and `u128`,
+/// and so does `BigUint` if the `num-bigint` feature is enabled.
 pub trait FractranNat:
-    Into<u64>
-    + Mul<Self, Output = Self>
-    + Div<Self, Output = Self>
-    + Divides
-    + Clone
-    + std::fmt::Debug
-    + Sized
+    Mul<Self, Output = Self> + Div<Self, Output = Self> + Divides + Clone + fmt::Debug + Sized
 {
+    /// Returns the number as a `u64`, or `None` if it's too large to fit.
+    fn to_u64_checked(&self) -> Option<u64>;
+
+    /// Returns `true` if the number is zero, which can't appear in a fraction.
+    fn is_zero(&self) -> bool;
+
+    /// Returns `true` if the number is one, the state most programs halt in.
+    fn is_one(&self) -> bool;
+
+    /// Returns the product of this number and `rhs`, or `None` if the product
+    /// is too large to represent.
+    fn checked_mul(&self, rhs: &Self) -> Option<Self>;
+
+    /// Returns `true` if this number divides the product `lhs * rhs`. By
+    /// default this computes the product, but types that can answer without
+    /// allocating a new number should do so, because this is checked for every
+    /// fraction on every step of execution.
+    fn divides_product(&self, lhs: &Self, rhs: &Self) -> bool {
+        self.divides(&(lhs.clone() * rhs.clone()))
+    }
+
+    /// Returns `Some(exp)` if this number is `prime^exp`, and `None` otherwise.
+    /// This is how FRACTRAN programs conventionally signal an output, so it
+    /// lets output detection work over any `FractranNat`. Note that 1 is
+    /// `prime^0`. `prime` is assumed to be prime: values below 2 give `None`.
+    fn is_power_of(&self, prime: u64) -> Option<u32>;
+
+    /// Returns the greatest common divisor of this number and `other`.
+    fn gcd(&self, other: &Self) -> Self;
 }
-impl<T> FractranNat for T where
-    T: Into<u64>
-        + Mul<Self, Output = Self>
-        + Div<Self, Output = Self>
-        + Divides
-        + Clone
-        + std::fmt::Debug
-        + Sized
-{
+
+// implement this for the primitive unsigned integers
+macro_rules! impl_fractran_nat_for_prim {
+    ($($t:ty),*) => {
+        $(
+            impl FractranNat for $t {
+                fn to_u64_checked(&self) -> Option<u64> {
+                    u64::try_from(*self).ok()
+                }
+
+                fn is_zero(&self) -> bool {
+                    *self == 0
+                }
+
+                fn is_one(&self) -> bool {
+                    *self == 1
+                }
+
+                fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+                    <$t>::checked_mul(*self, *rhs)
+                }
+
+                fn gcd(&self, other: &Self) -> Self {
+                    let (mut a, mut b) = (*self, *other);
+                    while b != 0 {
+                        let r = a % b;
+                        a = b;
+                        b = r;
+                    }
+                    a
+                }
+
+                fn is_power_of(&self, prime: u64) -> Option<u32> {
+                    if prime < 2 || *self == 0 {
+                        return None;
+                    }
+                    let prime = match <$t>::try_from(prime) {
+                        Ok(prime) => prime,
+                        // too large to be a factor, so only 1 is a power of it
+                        Err(_) => return if *self == 1 { Some(0) } else { None },
+                    };
+                    let (mut curr, mut exp) = (*self, 0);
+                    while curr % prime == 0 {
+                        curr /= prime;
+                        exp += 1;
+                    }
+                    if curr == 1 {
+                        Some(exp)
+                    } else {
+                        None
+                    }
+                }
+            }
+        )*
+    };
 }
 
-/// A fraction in Fractran, with a nonzero numerator and denominator.
+impl_fractran_nat_for_prim!(u8, u16, u32, u64, u128);
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum FracError {
+    #[error("Cannot have fraction with zero in the numerator")]
+    ZeroNumerator,
+
+    #[error("Cannot have fraction with zero in the denominator")]
+    ZeroDenominator,
+}
+
+/// A fraction in Fractran, with a nonzero numerator and denominator. Fractions
+/// are always stored in lowest terms, so `4/6` and `2/3` are the same fraction.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Fraction<T: FractranNat> {
     /// The fraction numerator: must be nonzero.
@@ -41,41 +126,169 @@ pub struct Fraction<T: FractranNat> {
 /// Note that the result of a step can be `Changed` and still equal to the
 /// original state, if the fraction used was 1/1.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-pub(crate) enum StepResult<T: FractranNat> {
+pub enum StepResult<T: FractranNat> {
     /// The same state as before, without any multiplication.
     Unchanged(T),
     /// The product of the former state and the fraction at this step.
     Changed(T),
 }
 
+impl<T: FractranNat> StepResult<T> {
+    /// Returns `true` if the fraction applied.
+    pub fn is_changed(&self) -> bool {
+        matches!(self, StepResult::Changed(_))
+    }
+
+    /// Returns the state after the step, whether or not the fraction applied.
+    pub fn into_state(self) -> T {
+        match self {
+            StepResult::Unchanged(state) | StepResult::Changed(state) => state,
+        }
+    }
+}
+
 impl<T: FractranNat> Fraction<T> {
     /// Creates a new `Fraction` with the given numerator and denominator,
-    /// panicking if either input is zero.
+    /// panicking if either input is zero. The fraction is stored in lowest
+    /// terms, which doesn't change how it acts on a state but means `exec` can
+    /// divide before it multiplies.
     pub fn new(num: T, denom: T) -> Fraction<T> {
-        if num.clone().into() == 0_u64 || denom.clone().into() == 0_u64 {
-            panic!("Cannot have fraction with zero on either side!");
+        match Fraction::try_new(num, denom) {
+            Ok(frac) => frac,
+            Err(_) => panic!("Cannot have fraction with zero on either side!"),
+        }
+    }
+
+    /// Like `new`, but returns `ZeroNumerator` or `ZeroDenominator` instead of
+    /// panicking if either input is zero, for fractions from untrusted input.
+    pub fn try_new(num: T, denom: T) -> Result<Fraction<T>, FracError> {
+        if num.is_zero() {
+            Err(FracError::ZeroNumerator)
+        } else if denom.is_zero() {
+            Err(FracError::ZeroDenominator)
         } else {
-            Fraction { num, denom }
+            Ok(Fraction { num, denom }.lowest_terms())
         }
     }
+
+    /// Returns the numerator of the fraction.
+    pub fn num(&self) -> &T {
+        &self.num
+    }
+
+    /// Returns the denominator of the fraction.
+    pub fn denom(&self) -> &T {
+        &self.denom
+    }
+
+    /// Returns the same fraction, which is already in lowest terms: every
+    /// `Fraction` is reduced when it's made.
+    #[deprecated(note = "fractions are always stored in lowest terms")]
+    pub fn reduced(self) -> Fraction<T> {
+        self
+    }
+
+    /// Divides the numerator and denominator by their greatest common divisor.
+    /// This never changes how the fraction acts on a state.
+    fn lowest_terms(self) -> Fraction<T> {
+        let gcd = self.num.gcd(&self.denom);
+        Fraction {
+            num: self.num / gcd.clone(),
+            denom: self.denom / gcd,
+        }
+    }
+
+    /// Returns `true` if the numerator and denominator are equal, so that the
+    /// fraction applies to every state without changing it. A program that
+    /// reaches such a fraction loops forever instead of halting.
+    pub fn is_identity(&self) -> bool
+    where
+        T: PartialEq,
+    {
+        self.num == self.denom
+    }
+
+    /// Returns `true` if the fraction applies to every state, which happens
+    /// exactly when the denominator divides the numerator: `x/1`, identities
+    /// like `3/3`, and fractions like `6/3` all do.
+    pub fn always_applies(&self) -> bool {
+        self.denom.divides(&self.num)
+    }
+
     /// Computes the only operation Fractran has: for this fraction `f` and some
     /// input `n`, returns `StepResult::Changed(nf)` if `nf` is integral and
     /// `StepResult::Unchanged(n)` otherwise. Note that, for example, 1/1
     /// doesn't change the actual state, but it will still return `Changed`
     /// because the multiplication was performed.
-    pub(crate) fn exec(&self, input: T) -> StepResult<T> {
-        let new_num = input.clone() * self.clone().num;
-        if self.denom.divides(&new_num) {
-            StepResult::Changed(new_num / self.clone().denom)
+    pub fn exec(&self, input: T) -> StepResult<T> {
+        match self.exec_ref(&input) {
+            Some(new_state) => StepResult::Changed(new_state),
+            None => StepResult::Unchanged(input),
+        }
+    }
+
+    /// Like `exec`, but borrows the input, returning `Some(nf)` if `nf` is
+    /// integral and `None` otherwise. A new state is only allocated if the
+    /// fraction actually applies, which makes this the cheapest way to step.
+    pub(crate) fn exec_ref(&self, input: &T) -> Option<T> {
+        // in lowest terms, nf is integral exactly when the denominator divides
+        // n, and dividing first keeps the intermediate value small
+        if self.denom.divides(input) {
+            Some(input.clone() / self.denom.clone() * self.num.clone())
         } else {
-            StepResult::Unchanged(input)
+            None
+        }
+    }
+
+    /// Like `exec_ref`, but checks the multiplication for overflow instead of
+    /// silently overflowing: returns `None` if the fraction doesn't apply,
+    /// `Some(None)` if it applies but the new state can't be represented, and
+    /// `Some(Some(nf))` otherwise. The input is only cloned if the fraction
+    /// applies.
+    pub(crate) fn exec_checked_ref(&self, input: &T) -> Option<Option<T>> {
+        if self.denom.divides(input) {
+            let quotient = input.clone() / self.denom.clone();
+            Some(quotient.checked_mul(&self.num))
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: FractranNat> Mul for Fraction<T> {
+    type Output = Fraction<T>;
+
+    /// Multiplies the numerators and denominators, so `(a/b) * (c/d)` is
+    /// `ac/bd`, reduced to lowest terms like every `Fraction`. Applying
+    /// the product to a state gives the same result as applying `a/b` and then
+    /// `c/d` whenever both of those apply, but the product can also apply when
+    /// they don't: `(3/2) * (2/3)` applies to everything.
+    fn mul(self, rhs: Fraction<T>) -> Fraction<T> {
+        Fraction {
+            num: self.num * rhs.num,
+            denom: self.denom * rhs.denom,
         }
+        .lowest_terms()
     }
 }
 
 impl<T: FractranNat + fmt::Display> fmt::Display for Fraction<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} / {}", self.num, self.denom)
+        write!(f, "{}/{}", self.num, self.denom)
+    }
+}
+
+impl<T: FractranNat + fmt::Display> fmt::Display for StepResult<T> {
+    /// Writes the variant around the state, like `Changed(3)`. Flags are
+    /// passed on to the state, so `{:#}` shows a `PrimeBasis` as a number.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, state) = match self {
+            StepResult::Unchanged(state) => ("Unchanged", state),
+            StepResult::Changed(state) => ("Changed", state),
+        };
+        write!(f, "{}(", name)?;
+        fmt::Display::fmt(state, f)?;
+        write!(f, ")")
     }
 }
 
@@ -83,6 +296,20 @@ impl<T: FractranNat + fmt::Display> fmt::Display for Fraction<T> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_try_new() {
+        assert_eq!(Fraction::try_new(4_u64, 6), Ok(Fraction::new(2, 3)));
+        assert_eq!(Fraction::try_new(0_u64, 6), Err(FracError::ZeroNumerator));
+        assert_eq!(Fraction::try_new(4_u64, 0), Err(FracError::ZeroDenominator));
+        assert_eq!(Fraction::try_new(0_u64, 0), Err(FracError::ZeroNumerator));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_new_zero() {
+        Fraction::new(1_u64, 0);
+    }
+
     #[test]
     fn test_exec() {
         assert_eq!(
@@ -98,4 +325,118 @@ mod tests {
             StepResult::Changed(24_u64)
         );
     }
+
+    #[test]
+    fn test_step_result() {
+        let changed = Fraction::new(1_u64, 2_u64).exec(6);
+        assert!(changed.is_changed());
+        assert_eq!(changed.into_state(), 3);
+        let unchanged = Fraction::new(1_u64, 2_u64).exec(3);
+        assert!(!unchanged.is_changed());
+        assert_eq!(unchanged.into_state(), 3);
+    }
+
+    #[test]
+    fn test_step_result_display() {
+        let frac = Fraction::new(1_u64, 2_u64);
+        assert_eq!(frac.exec(6).to_string(), "Changed(3)");
+        assert_eq!(frac.exec(3).to_string(), "Unchanged(3)");
+
+        let pb = |n| crate::primebasis::PrimeBasis::try_new(n).unwrap();
+        let frac = Fraction::new(pb(5), pb(2));
+        assert_eq!(
+            frac.clone().exec(pb(4)).to_string(),
+            "Changed(PrimeBasis(2^1 ✕ 5^1))"
+        );
+        assert_eq!(format!("{:#}", frac.exec(pb(4))), "Changed(10)");
+    }
+
+    #[test]
+    fn test_is_identity() {
+        assert!(Fraction::new(1_u64, 1).is_identity());
+        assert!(Fraction::new(7_u64, 7).is_identity());
+        assert!(!Fraction::new(6_u64, 3).is_identity());
+        assert!(!Fraction::new(3_u64, 6).is_identity());
+    }
+
+    #[test]
+    fn test_always_applies() {
+        assert!(Fraction::new(5_u64, 1).always_applies());
+        assert!(Fraction::new(3_u64, 3).always_applies());
+        assert!(Fraction::new(6_u64, 3).always_applies());
+        assert!(!Fraction::new(3_u64, 6).always_applies());
+        assert!(!Fraction::new(5_u64, 2).always_applies());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_reduced() {
+        assert_eq!(Fraction::new(4_u64, 6).reduced(), Fraction::new(2, 3));
+        assert_eq!(Fraction::new(7_u64, 7).reduced(), Fraction::new(1, 1));
+        assert_eq!(Fraction::new(5_u64, 3).reduced(), Fraction::new(5, 3));
+        assert_eq!(Fraction::new(12_u8, 1).reduced(), Fraction::new(12, 1));
+    }
+
+    #[test]
+    fn test_mul() {
+        let (first, second) = (Fraction::new(3_u64, 2), Fraction::new(5_u64, 9));
+        let product = first.clone() * second.clone();
+        assert_eq!(product, Fraction::new(5, 6));
+        assert_eq!(product.num(), &5);
+
+        // same result whenever both steps apply one after the other
+        for n in 1..200_u64 {
+            if let Some(mid) = first.exec_ref(&n) {
+                if let Some(end) = second.exec_ref(&mid) {
+                    assert_eq!(product.exec_ref(&n), Some(end));
+                }
+            }
+        }
+        // but the product can apply even when the steps don't: 3/2 doesn't
+        // apply to 1, but (3/2) * (2/3) = 1/1 applies to everything
+        let undo = first.clone() * Fraction::new(2, 3);
+        assert_eq!(first.exec_ref(&1), None);
+        assert_eq!(undo.exec_ref(&1), Some(1));
+    }
+
+    #[test]
+    fn test_exec_ref() {
+        assert_eq!(Fraction::new(1_u64, 2_u64).exec_ref(&2_u64), Some(1_u64));
+        assert_eq!(Fraction::new(1_u64, 2_u64).exec_ref(&1_u64), None);
+        assert_eq!(Fraction::new(6_u64, 7_u64).exec_ref(&28_u64), Some(24_u64));
+        // the denominator can be cancelled by the numerator
+        assert_eq!(Fraction::new(6_u64, 4_u64).exec_ref(&2_u64), Some(3_u64));
+    }
+
+    #[test]
+    fn test_exec_checked_ref() {
+        assert_eq!(
+            Fraction::new(6_u64, 7_u64).exec_checked_ref(&28_u64),
+            Some(Some(24_u64))
+        );
+        assert_eq!(
+            Fraction::new(3_u64, 2_u64).exec_checked_ref(&(u64::MAX - 1)),
+            Some(None)
+        );
+        // an odd number is left alone, so there's nothing to overflow
+        assert_eq!(
+            Fraction::new(3_u64, 2_u64).exec_checked_ref(&u64::MAX),
+            None
+        );
+        // 3/6 is stored as 1/2, so this doesn't overflow even though
+        // 2^63 * 3 doesn't fit
+        assert_eq!(
+            Fraction::new(3_u64, 6_u64).exec_checked_ref(&(1 << 63)),
+            Some(Some(1 << 62))
+        );
+    }
+
+    #[test]
+    fn test_new_reduces() {
+        let frac = Fraction::new(4_u64, 6_u64);
+        assert_eq!((frac.num(), frac.denom()), (&2, &3));
+        assert_eq!(frac, Fraction::new(2, 3));
+        assert_eq!(frac.exec(9), StepResult::Changed(6));
+        assert_eq!(frac.exec(2), StepResult::Unchanged(2));
+    }
 }
@@ -1,9 +1,18 @@
 //! A Fractran interpreter written in Rust.
+//!
+//! The `std` feature is on by default. Without it, the crate only needs
+//! `alloc`, and the optional `libm` feature provides the logarithms that would
+//! otherwise come from `std`, instead of a slower series. Only the few methods
+//! that need `std`'s hash maps, clock, or I/O are left out.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
 #[macro_use]
 extern crate lazy_static;
 
-mod math;
+pub mod math;
 
 /// N, where the nth prime is the largest one allowed as a factor of an input:
 /// intuitively, the number of registers the program can read and write to. For
@@ -12,14 +21,22 @@ mod math;
 pub const MAX_REGS: u16 = 1000;
 
 lazy_static! {
-    /// The list of the first `MAX_REGS` primes, generated at run time from
-    /// `MAX_REGS` and then saved for all future uses.
-    pub static ref PRIMES: Vec<u64> = math::first_n_primes(MAX_REGS);
+    /// The table of the first `MAX_REGS` primes, generated at run time from
+    /// `MAX_REGS` and then saved for all future uses. Operations that don't
+    /// take a `PrimeTable` explicitly use this one.
+    pub static ref PRIMES: math::PrimeTable = math::PrimeTable::default();
 }
 
+#[cfg(feature = "num-bigint")]
+mod bigint;
+pub mod extended;
+pub mod fixedbasis;
 pub mod frac;
+pub mod named;
+pub mod parse;
 pub mod primebasis;
 pub mod program;
+pub mod register_machine;
 
 #[cfg(test)]
 mod tests {
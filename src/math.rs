@@ -1,19 +1,108 @@
 //! A module providing mathematical utility functions required for operating
 //! FRACTRAN code.
 
-/// Computes the first n primes as a `Vec` using the Sieve of Eratosthenes.
-pub fn first_n_primes(n: u16) -> Vec<u64> {
-    // for all n >= 6, p_n < n(log n + log log n)
+use alloc::{vec, vec::Vec};
+use core::ops::Deref;
+
+/// Returns the base-2 logarithm of `x`, which must be at least 1. This uses
+/// `std`, or `libm` without it, and otherwise falls back on `log2_series`.
+pub(crate) fn log2(x: f64) -> f64 {
+    #[cfg(feature = "std")]
+    return x.log2();
+    #[cfg(all(not(feature = "std"), feature = "libm"))]
+    return libm::log2(x);
+    #[cfg(not(any(feature = "std", feature = "libm")))]
+    return log2_series(x);
+}
+
+/// Computes the base-2 logarithm of `x`, which must be at least 1, with only
+/// basic arithmetic. `x` is halved down to `m` in `[1, 2)`, counting the
+/// halvings, and `log2(m)` comes from the series `ln(m) = 2 atanh(z)` with
+/// `z = (m - 1) / (m + 1)`, which is at most 1/3, so a few dozen terms reach
+/// full precision.
+#[cfg_attr(any(feature = "std", feature = "libm"), allow(dead_code))]
+fn log2_series(mut x: f64) -> f64 {
+    let mut whole = 0.0;
+    while x >= 2.0 {
+        x /= 2.0;
+        whole += 1.0;
+    }
+    let z = (x - 1.0) / (x + 1.0);
+    let (mut term, mut sum) = (z, 0.0);
+    for k in 0..30 {
+        sum += term / (2 * k + 1) as f64;
+        term *= z * z;
+    }
+    whole + 2.0 * sum / core::f64::consts::LN_2
+}
+
+/// Returns the number of bits needed to write `n` in binary, which is more
+/// than `log2(n)` and so more than `ln(n)`.
+fn bit_length(n: u64) -> u64 {
+    (64 - n.leading_zeros()) as u64
+}
+
+/// An upper bound on the nth prime (counting from 1), suitable for sizing a
+/// sieve that needs to find the first n primes. This only uses integer
+/// arithmetic, so it needs neither `std` nor `libm`.
+pub fn nth_prime_upper_bound(n: usize) -> u64 {
+    // for all n >= 6, p_n < n(ln n + ln ln n), and ln is less than the bit
+    // length, so p_n < n(bits(n) + bits(bits(n)))
     // otherwise, because p_5 = 11, p_n < 11
-    let max_prime: usize = if n < 6 {
+    if n < 6 {
         11
     } else {
-        (n as f64 * ((n as f64).ln() + (n as f64).ln().ln())).floor() as usize
+        let n = n as u64;
+        n * (bit_length(n) + bit_length(bit_length(n)))
+    }
+}
+
+/// Returns `true` if `n` is prime, for numbers too large to sieve up to. This
+/// is the Miller-Rabin test with the first twelve primes as witnesses, which
+/// is known to make no mistakes for any `u64`.
+pub fn is_prime(n: u64) -> bool {
+    const WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    if let Some(&p) = WITNESSES.iter().find(|&&p| n % p == 0) {
+        return n == p;
+    }
+    let mul_mod = |a: u64, b: u64| (a as u128 * b as u128 % n as u128) as u64;
+    let pow_mod = |mut base: u64, mut exp: u64| {
+        let mut acc = 1;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                acc = mul_mod(acc, base);
+            }
+            base = mul_mod(base, base);
+            exp >>= 1;
+        }
+        acc
     };
+    // n - 1 = d * 2^s with d odd
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    WITNESSES.iter().all(|&a| {
+        let mut x = pow_mod(a, d);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        (1..s).any(|_| {
+            x = mul_mod(x, x);
+            x == n - 1
+        })
+    })
+}
 
-    let mut primes: Vec<bool> = vec![true; max_prime as usize + 1];
+/// Runs the Sieve of Eratosthenes, returning every prime `<= max_prime` in
+/// increasing order.
+fn sieve(max_prime: usize) -> Vec<u64> {
+    let mut primes: Vec<bool> = vec![true; max_prime + 1];
     primes[0] = false;
-    primes[1] = false;
+    if max_prime >= 1 {
+        primes[1] = false;
+    }
     for i in 2..=max_prime {
         if primes[i] {
             let mut curr_mult = i * 2;
@@ -24,13 +113,97 @@ pub fn first_n_primes(n: u16) -> Vec<u64> {
         }
     }
 
-    return primes
+    primes
         .into_iter()
         .enumerate()
         .filter(|(_, is_p)| *is_p)
         .map(|(i, _)| i as u64)
-        .take(n as usize)
-        .collect();
+        .collect()
+}
+
+/// Computes the first n primes as a `Vec` using the Sieve of Eratosthenes.
+pub fn first_n_primes(n: u16) -> Vec<u64> {
+    let mut primes = sieve(nth_prime_upper_bound(n as usize) as usize);
+    primes.truncate(n as usize);
+    primes
+}
+
+/// Computes every prime less than or equal to `n`, in increasing order. Unlike
+/// [`first_n_primes`], this is not limited by the number of registers, so it
+/// can be used to build a larger prime table at run time when an input needs
+/// primes beyond `PRIMES`.
+pub fn primes_up_to(n: u64) -> Vec<u64> {
+    sieve(n as usize)
+}
+
+/// An owned, growable table of the first primes in increasing order. The
+/// global `PRIMES` is one of these, sized by `MAX_REGS`, but programs that
+/// need fewer registers can use a smaller table, and ones that need more can
+/// use a larger table or extend one when a large input shows up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrimeTable {
+    primes: Vec<u64>,
+}
+
+impl PrimeTable {
+    /// Creates a table of the first `n` primes.
+    pub fn new(n: usize) -> PrimeTable {
+        let mut primes = sieve(nth_prime_upper_bound(n) as usize);
+        primes.truncate(n);
+        PrimeTable { primes }
+    }
+
+    /// Creates a table of every prime less than or equal to `max_prime`.
+    pub fn up_to(max_prime: u64) -> PrimeTable {
+        PrimeTable {
+            primes: primes_up_to(max_prime),
+        }
+    }
+
+    /// Grows the table so it holds at least the first `n` primes. Does nothing
+    /// if it's already that large.
+    pub fn extend_to(&mut self, n: usize) {
+        if n > self.primes.len() {
+            *self = PrimeTable::new(n);
+        }
+    }
+
+    /// Grows the table so it holds every prime less than or equal to
+    /// `max_prime`. Does nothing if it already does.
+    pub fn extend_up_to(&mut self, max_prime: u64) {
+        if self.primes.last().map_or(true, |&p| p < max_prime) {
+            *self = PrimeTable::up_to(max_prime);
+        }
+    }
+
+    /// Returns the primes in the table, in increasing order.
+    pub fn primes(&self) -> &[u64] {
+        &self.primes
+    }
+}
+
+impl Default for PrimeTable {
+    /// The table of the first `MAX_REGS` primes, the same as `PRIMES`.
+    fn default() -> PrimeTable {
+        PrimeTable::new(super::MAX_REGS as usize)
+    }
+}
+
+impl Deref for PrimeTable {
+    type Target = [u64];
+
+    fn deref(&self) -> &[u64] {
+        &self.primes
+    }
+}
+
+impl<'a> IntoIterator for &'a PrimeTable {
+    type Item = &'a u64;
+    type IntoIter = core::slice::Iter<'a, u64>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.primes.iter()
+    }
 }
 
 #[cfg(test)]
@@ -53,4 +226,60 @@ mod tests {
 
         assert_eq!(first_n_primes(0), vec![]);
     }
+
+    #[test]
+    fn primes_up_to_matches_first_n_primes() {
+        assert_eq!(primes_up_to(0), vec![]);
+        assert_eq!(primes_up_to(1), vec![]);
+        assert_eq!(primes_up_to(2), vec![2]);
+        assert_eq!(primes_up_to(37), first_n_primes(12));
+        assert_eq!(primes_up_to(40), first_n_primes(12));
+
+        let bound = nth_prime_upper_bound(2000);
+        let primes = primes_up_to(bound);
+        assert!(primes.len() >= 2000);
+        assert_eq!(primes[..1000], first_n_primes(1000)[..]);
+    }
+
+    #[test]
+    fn nth_prime_upper_bound_holds() {
+        let primes = primes_up_to(20_000);
+        for (i, &p) in primes.iter().enumerate() {
+            assert!(p <= nth_prime_upper_bound(i + 1), "p_{} = {}", i + 1, p);
+        }
+    }
+
+    #[test]
+    fn log2_series_matches_log2() {
+        for &x in &[1.0, 1.5, 2.0, 3.0, 7927.0, 1e300, u64::MAX as f64] {
+            let expected: f64 = x.log2();
+            assert!((log2_series(x) - expected).abs() <= 1e-12 * (1.0 + expected));
+        }
+    }
+
+    #[test]
+    fn is_prime_matches_sieve() {
+        let primes = primes_up_to(10_000);
+        for n in 0..=10_000 {
+            assert_eq!(is_prime(n), primes.binary_search(&n).is_ok(), "{}", n);
+        }
+        // the largest prime below 2^64, and a strong pseudoprime to base 2
+        assert!(is_prime(18_446_744_073_709_551_557));
+        assert!(!is_prime(3_215_031_751));
+        assert!(!is_prime(89 * 89));
+    }
+
+    #[test]
+    fn prime_table_grows() {
+        let mut table = PrimeTable::new(5);
+        assert_eq!(table.primes(), &[2, 3, 5, 7, 11]);
+        table.extend_to(3);
+        assert_eq!(table.len(), 5);
+        table.extend_to(12);
+        assert_eq!(table.primes(), &first_n_primes(12)[..]);
+        table.extend_up_to(50);
+        assert_eq!(table.last(), Some(&47));
+        assert_eq!(PrimeTable::up_to(37), PrimeTable::new(12));
+        assert_eq!(PrimeTable::default().len(), super::super::MAX_REGS as usize);
+    }
 }
@@ -0,0 +1,216 @@
+//! Provides a symbolic way to write Fractran programs over `PrimeBasis`, where
+//! registers are declared by name and fractions are written in terms of those
+//! names instead of raw primes.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use thiserror::Error;
+
+use super::frac::Fraction;
+use super::primebasis::{self, PrimeBasis};
+use super::program::{Program, ProgramError};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum NamedError {
+    #[error("Register `{0}` was used without being declared")]
+    UnknownRegister(String),
+
+    #[error("Register `{0}` was declared more than once")]
+    DuplicateRegister(String),
+
+    #[error("Prime {0} was given to more than one register")]
+    DuplicatePrime(u64),
+
+    #[error(transparent)]
+    Basis(#[from] primebasis::Error),
+
+    #[error(transparent)]
+    Program(#[from] ProgramError),
+}
+
+/// A list of `(register name, amount)` pairs, standing for the product of each
+/// register's prime raised to its amount.
+type Term = Vec<(String, u32)>;
+
+/// A Fractran program written with named registers. Each register is declared
+/// with the prime that stores it, and each fraction lists how much it takes
+/// from (denominator) and adds to (numerator) each register by name. Nothing
+/// is checked until `compile()`, which lowers it to a `Program<PrimeBasis>`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NamedProgram {
+    /// The declared registers and their primes, in order of declaration.
+    registers: Vec<(String, u64)>,
+    /// The numerator and denominator of each fraction, in order.
+    fracs: Vec<(Term, Term)>,
+}
+
+/// Converts borrowed `(name, amount)` pairs to an owned `Term`.
+fn to_term(pairs: &[(&str, u32)]) -> Term {
+    pairs
+        .iter()
+        .map(|&(name, amount)| (name.to_string(), amount))
+        .collect()
+}
+
+impl NamedProgram {
+    /// Makes a program with no registers or fractions.
+    pub fn new() -> NamedProgram {
+        NamedProgram::default()
+    }
+
+    /// Declares a register called `name`, stored as the exponent of `prime`.
+    pub fn register(mut self, name: &str, prime: u64) -> NamedProgram {
+        self.registers.push((name.to_string(), prime));
+        self
+    }
+
+    /// Adds a fraction after the ones already added, which adds `numerator` to
+    /// the registers and takes away `denominator`, each given as
+    /// `(name, amount)` pairs. For example, with `a` as 2 and `b` as 3,
+    /// `frac(&[("b", 1)], &[("a", 2)])` is the fraction 3/4.
+    pub fn frac(mut self, numerator: &[(&str, u32)], denominator: &[(&str, u32)]) -> NamedProgram {
+        self.fracs.push((to_term(numerator), to_term(denominator)));
+        self
+    }
+
+    /// Returns the declared registers and their primes, in order of
+    /// declaration.
+    pub fn registers(&self) -> &[(String, u64)] {
+        &self.registers
+    }
+
+    /// Checks the register declarations and returns a map from each name to
+    /// its prime.
+    fn primes_by_name(&self) -> Result<BTreeMap<&str, u64>, NamedError> {
+        let mut primes = BTreeMap::new();
+        for (name, prime) in &self.registers {
+            if primes.values().any(|p| p == prime) {
+                return Err(NamedError::DuplicatePrime(*prime));
+            }
+            if primes.insert(name.as_str(), *prime).is_some() {
+                return Err(NamedError::DuplicateRegister(name.clone()));
+            }
+        }
+        Ok(primes)
+    }
+
+    /// Converts a term to a `PrimeBasis` using the given register primes.
+    fn term_to_basis(
+        term: &[(String, u32)],
+        primes: &BTreeMap<&str, u64>,
+    ) -> Result<PrimeBasis, NamedError> {
+        let factors = term
+            .iter()
+            .map(|(name, amount)| match primes.get(name.as_str()) {
+                Some(&prime) => Ok((prime, *amount)),
+                None => Err(NamedError::UnknownRegister(name.clone())),
+            })
+            .collect::<Result<Vec<(u64, u32)>, NamedError>>()?;
+        Ok(PrimeBasis::from_factors(&factors)?)
+    }
+
+    /// Makes the state with the given register values, each given as
+    /// `(name, value)` pairs, for use as the input to the compiled program.
+    /// Registers that aren't mentioned are 0.
+    pub fn state(&self, values: &[(&str, u32)]) -> Result<PrimeBasis, NamedError> {
+        NamedProgram::term_to_basis(&to_term(values), &self.primes_by_name()?)
+    }
+
+    /// Returns the value of the register called `name` in `state`, or `None` if
+    /// no such register was declared or its prime can't be stored.
+    pub fn read(&self, state: &PrimeBasis, name: &str) -> Option<u32> {
+        let &(_, prime) = self.registers.iter().find(|(n, _)| n == name)?;
+        let index = super::PRIMES.binary_search(&prime).ok()?;
+        Some(state.register(index))
+    }
+
+    /// Lowers this program to a `Program<PrimeBasis>`. Returns
+    /// `DuplicateRegister` or `DuplicatePrime` if the declarations conflict,
+    /// `UnknownRegister` if a fraction uses an undeclared register, `Basis` if
+    /// a declared prime isn't prime or is too large to store, and `Program`
+    /// if there are no fractions.
+    pub fn compile(&self) -> Result<Program<PrimeBasis>, NamedError> {
+        let primes = self.primes_by_name()?;
+        let fracs = self
+            .fracs
+            .iter()
+            .map(|(num, denom)| {
+                Ok(Fraction::new(
+                    NamedProgram::term_to_basis(num, &primes)?,
+                    NamedProgram::term_to_basis(denom, &primes)?,
+                ))
+            })
+            .collect::<Result<Vec<Fraction<PrimeBasis>>, NamedError>>()?;
+        Ok(Program::try_new(fracs)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The multiplication program `455/33, 11/13, 1/11, 3/7, 11/2, 1/3`, which
+    /// computes `a * b` into `c`, written with names.
+    fn multiply() -> NamedProgram {
+        NamedProgram::new()
+            .register("a", 2)
+            .register("b", 3)
+            .register("c", 5)
+            .register("d", 7)
+            .register("t", 11)
+            .register("u", 13)
+            .frac(&[("c", 1), ("d", 1), ("u", 1)], &[("b", 1), ("t", 1)])
+            .frac(&[("t", 1)], &[("u", 1)])
+            .frac(&[], &[("t", 1)])
+            .frac(&[("b", 1)], &[("d", 1)])
+            .frac(&[("t", 1)], &[("a", 1)])
+            .frac(&[], &[("b", 1)])
+    }
+
+    #[test]
+    fn test_compile() {
+        let named = multiply();
+        let prog = named.compile().unwrap();
+        assert_eq!(
+            prog,
+            Program::from_u64_fractions(&[(455, 33), (11, 13), (1, 11), (3, 7), (11, 2), (1, 3)])
+                .unwrap()
+        );
+
+        let input = named.state(&[("a", 3), ("b", 4)]).unwrap();
+        let output = prog.exec_to_completion(input);
+        assert_eq!(named.read(&output, "c"), Some(12));
+        assert_eq!(named.read(&output, "a"), Some(0));
+        assert_eq!(named.read(&output, "z"), None);
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let undeclared = multiply().frac(&[("z", 1)], &[]);
+        assert_eq!(
+            undeclared.compile(),
+            Err(NamedError::UnknownRegister("z".to_string()))
+        );
+        assert_eq!(
+            multiply().register("a", 17).compile(),
+            Err(NamedError::DuplicateRegister("a".to_string()))
+        );
+        assert_eq!(
+            multiply().register("e", 2).compile(),
+            Err(NamedError::DuplicatePrime(2))
+        );
+        assert_eq!(
+            NamedProgram::new()
+                .register("a", 4)
+                .frac(&[("a", 1)], &[])
+                .compile(),
+            Err(NamedError::Basis(primebasis::Error::NotPrime(4)))
+        );
+        assert_eq!(
+            NamedProgram::new().register("a", 2).compile(),
+            Err(NamedError::Program(ProgramError::EmptyProgram))
+        );
+    }
+}
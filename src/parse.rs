@@ -0,0 +1,193 @@
+//! Parses Fractran programs from the conventional textual notation, a list of
+//! fractions like `455/33, 11/13, 1/11, 3/7, 11/2, 1/3`.
+
+use alloc::string::{String, ToString};
+use alloc::{format, vec, vec::Vec};
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::io::BufRead;
+
+use thiserror::Error;
+
+use super::frac::Fraction;
+use super::program::{Program, ProgramError};
+
+#[derive(Error, Debug)]
+pub enum ParseError {
+    #[error("Could not parse `{0}` as a natural number")]
+    InvalidNumber(String),
+
+    #[error("Malformed fraction `{0}`: expected `numerator/denominator`")]
+    Malformed(String),
+
+    #[error("Fraction `{0}` has zero on one side, which is not allowed")]
+    ZeroInFraction(String),
+
+    #[error(transparent)]
+    Program(#[from] ProgramError),
+
+    #[cfg(feature = "std")]
+    #[error("Could not read program: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Parses a single side of a fraction.
+fn parse_nat(token: &str) -> Result<u64, ParseError> {
+    token
+        .parse()
+        .map_err(|_| ParseError::InvalidNumber(token.to_string()))
+}
+
+/// Parses every fraction in a single comma-delimited entry, which may itself
+/// contain several whitespace-separated fractions.
+fn parse_entry(entry: &str, fracs: &mut Vec<Fraction<u64>>) -> Result<(), ParseError> {
+    // pad the slashes so that `455/33` and `455 / 33` tokenize the same way
+    let padded = entry.replace('/', " / ");
+    let mut tokens = padded.split_whitespace();
+    while let Some(num) = tokens.next() {
+        let (slash, denom) = match (tokens.next(), tokens.next()) {
+            (Some(slash), Some(denom)) => (slash, denom),
+            _ => return Err(ParseError::Malformed(entry.trim().to_string())),
+        };
+        if slash != "/" || num == "/" || denom == "/" {
+            return Err(ParseError::Malformed(format!(
+                "{} {} {}",
+                num, slash, denom
+            )));
+        }
+        let (num_val, denom_val) = (parse_nat(num)?, parse_nat(denom)?);
+        match Fraction::try_new(num_val, denom_val) {
+            Ok(frac) => fracs.push(frac),
+            Err(_) => return Err(ParseError::ZeroInFraction(format!("{}/{}", num, denom))),
+        }
+    }
+    Ok(())
+}
+
+impl Program<u64> {
+    /// Parses a program written in the conventional notation: fractions of the
+    /// form `num/denom`, separated by commas, whitespace, or newlines. Blank
+    /// entries are skipped, and whitespace around slashes is ignored. Input
+    /// without any fractions is rejected as an empty program.
+    pub fn parse(s: &str) -> Result<Program<u64>, ParseError> {
+        let mut fracs = vec![];
+        for entry in s.split(',') {
+            parse_entry(entry, &mut fracs)?;
+        }
+        Ok(Program::try_new(fracs)?)
+    }
+
+    /// Reads a whole program from `reader`, like a `.fractran` file, and parses
+    /// it as with `parse`. Lines starting with `#`, ignoring leading
+    /// whitespace, are comments and are skipped. Returns `Io` if reading fails.
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Program<u64>, ParseError> {
+        let mut source = String::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim_start().starts_with('#') {
+                source.push_str(&line);
+                source.push('\n');
+            }
+        }
+        Program::parse(&source)
+    }
+}
+
+impl FromStr for Program<u64> {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Program::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes the expected program from a list of numerator/denominator pairs.
+    fn expected(pairs: &[(u64, u64)]) -> Program<u64> {
+        Program::new(
+            pairs
+                .iter()
+                .map(|&(num, denom)| Fraction::new(num, denom))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_parse_conventional() {
+        let prog = Program::parse("455/33, 11/13, 1/11, 3/7, 11/2, 1/3").unwrap();
+        assert_eq!(prog.len(), 6);
+        assert_eq!(prog.fractions()[4], Fraction::new(11, 2));
+        assert_eq!(
+            prog,
+            expected(&[(455, 33), (11, 13), (1, 11), (3, 7), (11, 2), (1, 3)])
+        );
+    }
+
+    #[test]
+    fn test_parse_whitespace_and_newlines() {
+        let prog = Program::parse("  455 / 33 ,,\n11/13\n\n 1/11 3 /7,\n").unwrap();
+        assert_eq!(prog, expected(&[(455, 33), (11, 13), (1, 11), (3, 7)]));
+        let from_str: Program<u64> = "455/33 11/13".parse().unwrap();
+        assert_eq!(from_str, expected(&[(455, 33), (11, 13)]));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let prog = expected(&[(17, 91), (78, 85), (19, 51), (55, 1)]);
+        assert_eq!(Program::parse(&prog.to_string()).unwrap(), prog);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_from_reader() {
+        let file = "# Conway's PRIMEGAME\n\
+                    17/91 78/85 19/51\n\
+                    \t# the middle row\n\
+                    23/38, 29/33\n";
+        let prog = Program::from_reader(file.as_bytes()).unwrap();
+        assert_eq!(
+            prog,
+            expected(&[(17, 91), (78, 85), (19, 51), (23, 38), (29, 33)])
+        );
+        assert!(matches!(
+            Program::from_reader("# nothing but comments\n".as_bytes()),
+            Err(ParseError::Program(ProgramError::EmptyProgram))
+        ));
+        assert!(matches!(
+            Program::from_reader(&[0xff, 0xfe, b'\n'][..]),
+            Err(ParseError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            Program::parse("455/33, 0/7"),
+            Err(ParseError::ZeroInFraction(token)) if token == "0/7"
+        ));
+        assert!(matches!(
+            Program::parse("3/0"),
+            Err(ParseError::ZeroInFraction(token)) if token == "3/0"
+        ));
+        assert!(matches!(
+            Program::parse("455/33, abc/7"),
+            Err(ParseError::InvalidNumber(token)) if token == "abc"
+        ));
+        assert!(matches!(
+            Program::parse("455/33, 11"),
+            Err(ParseError::Malformed(token)) if token == "11"
+        ));
+        assert!(matches!(
+            Program::parse("455 33 7"),
+            Err(ParseError::Malformed(_))
+        ));
+        assert!(matches!(
+            Program::parse(" ,\n, "),
+            Err(ParseError::Program(ProgramError::EmptyProgram))
+        ));
+    }
+}
@@ -2,24 +2,41 @@
 //! factorized form for computational efficiency when executing Fractran
 //! programs.
 
-use std::convert::{Into, TryFrom};
-use std::format;
-use std::ops::{Div, Mul, Rem};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::{vec, vec::Vec};
+use core::cmp::Ordering;
+use core::convert::TryFrom;
+use core::hash::{Hash, Hasher};
+use core::iter::Product;
+use core::ops::{Deref, Div, Mul};
 
 use itertools::EitherOrBoth;
 use itertools::Itertools;
+use spin::{RwLock, RwLockReadGuard};
 use thiserror::Error;
 
+use super::frac::FractranNat;
+use super::math::{self, PrimeTable};
 use super::PRIMES;
 
 #[derive(Error, Debug, PartialEq)]
 pub enum Error {
-    #[error("Register overflow: input {0} has prime factor larger than {}",
-            PRIMES.last().unwrap())]
+    #[error("Register overflow: input {0} has a prime factor too large for the prime table")]
     RegisterOverflow(u64),
 
     #[error("Zero is meaningless in FRACTRAN programs, cannot be stored")]
     NumIsZero,
+
+    #[error("{0} is not a prime, so it can't be used as a factor")]
+    NotPrime(u64),
+
+    #[error("Could not parse `{0}` as a product of prime powers")]
+    Malformed(String),
+
+    #[error("The exponent of {0} is too large to store in a u32")]
+    ExponentOverflow(u64),
 }
 
 /// Trait that expresses the ability to determine if a number divides another
@@ -30,38 +47,75 @@ pub trait Divides {
     fn divides(&self, rhs: &Self) -> bool;
 }
 
-// implement this for specifically u64 but anything else that happens to fit
-impl<T> Divides for T
-where
-    T: Rem<Self, Output = Self> + Into<u64> + Eq + Copy,
-{
-    fn divides(&self, rhs: &Self) -> bool {
-        (*rhs % *self).into() == 0
-    }
+// implement this for the primitive unsigned integers, which can just check the
+// remainder directly
+macro_rules! impl_divides_for_prim {
+    ($($t:ty),*) => {
+        $(
+            impl Divides for $t {
+                fn divides(&self, rhs: &Self) -> bool {
+                    *rhs % *self == 0
+                }
+            }
+        )*
+    };
 }
 
+impl_divides_for_prim!(u8, u16, u32, u64, u128);
+
 /// A natural number, represented as a vector of exponents in the prime
 /// factorization [a, b, c, ...] = 2^a * 3^b * 5^c * ...
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+///
+/// Equality and hashing ignore trailing zeros in `exps`, so they agree with
+/// equality of the numbers represented.
+#[derive(Clone, Debug)]
 pub struct PrimeBasis {
-    /// The vector of exponents. Can be at most `MAX_REGS`, but is not
-    /// guaranteed to be that long: any missing exponents are presumed to be 0.
+    /// The vector of exponents. Usually at most `MAX_REGS` long, though a basis
+    /// built with a larger `PrimeTable` can be longer, and not guaranteed to be
+    /// that long: any missing exponents are presumed to be 0.
     pub exps: Vec<u32>,
 }
 
+/// Bounds on how large the result of `PrimeBasis::mul_checked` can be. The
+/// default has no bounds, in which case only an exponent that doesn't fit in a
+/// `u32` is refused.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MulLimit {
+    /// The largest any one register (exponent) can be, if set.
+    pub max_exponent: Option<u32>,
+    /// The largest the base-2 logarithm of the number can be, as computed by
+    /// `approx_bit_length`, if set.
+    pub max_bit_length: Option<f64>,
+}
+
+/// Removes any zeros at the end of a list of exponents, which don't change the
+/// number it represents.
+fn trim_trailing_zeros(exps: &mut Vec<u32>) {
+    while exps.last() == Some(&0) {
+        exps.pop();
+    }
+}
+
 impl PrimeBasis {
     /// Attempts to create the prime basis representation of the given natural
     /// number. Returns `RegisterOverflow` if the number cannot be factored
     /// using the available space, and `NumIsZero` if the number given is zero,
     /// which has no prime factors.
     pub fn try_new(num: u64) -> Result<PrimeBasis, Error> {
+        PrimeBasis::try_new_in(num, &PRIMES)
+    }
+
+    /// Like `try_new`, but factors using the primes in `table` instead of the
+    /// global `PRIMES`, so that inputs with larger prime factors can be
+    /// represented.
+    pub fn try_new_in(num: u64, table: &PrimeTable) -> Result<PrimeBasis, Error> {
         if num == 0 {
             return Err(Error::NumIsZero);
         }
         let mut exps = vec![];
         let mut exp;
         let mut curr = num;
-        for prime in &*PRIMES {
+        for prime in table {
             if curr == 1 {
                 // we're done, return the value
                 return Ok(PrimeBasis { exps });
@@ -74,43 +128,509 @@ impl PrimeBasis {
             }
             exps.push(exp);
         }
+        if curr == 1 {
+            // the last prime in the table finished the factorization
+            return Ok(PrimeBasis { exps }.normalized());
+        }
         // if we reach here, didn't fully factor
         Err(Error::RegisterOverflow(num))
     }
 
-    /// Returns the number corresponding to this prime basis.
+    /// Creates the prime basis representation of the product of the given
+    /// `(prime, exponent)` pairs, without ever computing that product. Returns
+    /// `NotPrime` if any base isn't prime and `RegisterOverflow` if any base is
+    /// a prime too large to be stored. Repeated primes have their exponents
+    /// added together, returning `ExponentOverflow` if the total doesn't fit
+    /// in a `u32`.
+    pub fn from_factors(factors: &[(u64, u32)]) -> Result<PrimeBasis, Error> {
+        PrimeBasis::from_factors_in(factors, &PRIMES)
+    }
+
+    /// Like `from_factors`, but looks primes up in `table` instead of the
+    /// global `PRIMES`.
+    pub fn from_factors_in(
+        factors: &[(u64, u32)],
+        table: &PrimeTable,
+    ) -> Result<PrimeBasis, Error> {
+        let mut exps: Vec<u32> = vec![];
+        for &(prime, exp) in factors {
+            let index = match table.binary_search(&prime) {
+                Ok(index) => index,
+                Err(index) if index == table.len() && math::is_prime(prime) => {
+                    return Err(Error::RegisterOverflow(prime))
+                }
+                Err(_) => return Err(Error::NotPrime(prime)),
+            };
+            if exps.len() <= index {
+                exps.resize(index + 1, 0);
+            }
+            exps[index] = exps[index]
+                .checked_add(exp)
+                .ok_or(Error::ExponentOverflow(prime))?;
+        }
+        Ok(PrimeBasis { exps }.normalized())
+    }
+
+    /// Creates the prime basis representation of the number whose
+    /// factorization is given as a map from primes to exponents, like
+    /// `from_factors`. Returns `NotPrime` if any key isn't prime and
+    /// `RegisterOverflow` if any key is a prime too large to be stored.
+    pub fn from_factor_map(map: &BTreeMap<u64, u32>) -> Result<PrimeBasis, Error> {
+        let factors: Vec<(u64, u32)> = map.iter().map(|(&p, &exp)| (p, exp)).collect();
+        PrimeBasis::from_factors(&factors)
+    }
+
+    /// Parses a number written as a product of prime powers, like
+    /// `2^3 * 3^0 * 5^2`, without ever computing its value. Factors can be
+    /// separated by `*` or `✕`, an exponent of 1 can be left off, and `1`
+    /// stands for the empty product, so the output of `Display`, like
+    /// `PrimeBasis(2^3 ✕ 5^2)`, parses back to the same number. Returns
+    /// `Malformed` if the string isn't in this form, and `NotPrime`,
+    /// `RegisterOverflow`, or `NumIsZero` if a base can't be used.
+    pub fn from_godel_str(s: &str) -> Result<PrimeBasis, Error> {
+        PrimeBasis::from_godel_str_in(s, &PRIMES)
+    }
+
+    /// Like `from_godel_str`, but looks primes up in `table` instead of the
+    /// global `PRIMES`, so the output of `Display` for a basis built with a
+    /// larger table parses back with that table.
+    pub fn from_godel_str_in(s: &str, table: &PrimeTable) -> Result<PrimeBasis, Error> {
+        let s = s.trim();
+        let inner = s
+            .strip_prefix("PrimeBasis(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .unwrap_or(s);
+        let malformed = || Error::Malformed(s.to_string());
+        let mut factors = vec![];
+        for term in inner.split(['*', '✕']) {
+            let (base, exp) = match term.split_once('^') {
+                Some((base, exp)) => (base.trim(), exp.trim()),
+                None => (term.trim(), "1"),
+            };
+            let base: u64 = base.parse().map_err(|_| malformed())?;
+            let exp: u32 = exp.parse().map_err(|_| malformed())?;
+            match base {
+                0 => return Err(Error::NumIsZero),
+                // 1 to any power is 1, so it doesn't add a factor
+                1 => {}
+                _ => factors.push((base, exp)),
+            }
+        }
+        PrimeBasis::from_factors_in(&factors, table)
+    }
+
+    /// Returns the factorization of this number as a map from each prime
+    /// factor to its exponent, leaving out primes that don't divide it. Unlike
+    /// `exps`, this doesn't depend on the order of `PRIMES`.
+    pub fn to_factor_map(&self) -> BTreeMap<u64, u32> {
+        self.exps
+            .iter()
+            .zip(primes_for(&self.exps).iter())
+            .filter(|(&exp, _)| exp != 0)
+            .map(|(&exp, &p)| (p, exp))
+            .collect()
+    }
+
+    /// Creates the product of the first `n` primes, which is every register
+    /// from 0 to `n - 1` set to 1. Returns `RegisterOverflow(n)` if `n` is
+    /// more than the number of primes in `PRIMES`.
+    pub fn primorial(n: usize) -> Result<PrimeBasis, Error> {
+        if n > PRIMES.len() {
+            return Err(Error::RegisterOverflow(n as u64));
+        }
+        Ok(PrimeBasis { exps: vec![1; n] })
+    }
+
+    /// Creates `n!`, working out the exponent of each prime with Legendre's
+    /// formula instead of multiplying anything, so it works far beyond `20!`,
+    /// the largest factorial that fits in a `u64`. Returns `RegisterOverflow`
+    /// if `n` is larger than the largest prime in `PRIMES`, as then some prime
+    /// factor might not be stored.
+    pub fn factorial(n: u64) -> Result<PrimeBasis, Error> {
+        if PRIMES.last().is_some_and(|&p| n > p) {
+            return Err(Error::RegisterOverflow(n));
+        }
+        let exps = PRIMES
+            .iter()
+            .take_while(|&&p| p <= n)
+            .map(|&p| {
+                // the exponent of p in n! is n/p + n/p^2 + n/p^3 + ...
+                let mut exp = 0;
+                let mut quotient = n / p;
+                while quotient > 0 {
+                    exp += quotient as u32;
+                    quotient /= p;
+                }
+                exp
+            })
+            .collect();
+        Ok(PrimeBasis { exps })
+    }
+
+    /// Returns the exponent of the `i`th prime, thinking of it as the value of
+    /// the `i`th register. Registers past the end of `exps` are 0.
+    pub fn register(&self, i: usize) -> u32 {
+        self.exps.get(i).copied().unwrap_or(0)
+    }
+
+    /// Sets the exponent of the `i`th prime, thinking of it as the value of the
+    /// `i`th register, growing or shrinking `exps` so that it has no trailing
+    /// zeros.
+    pub fn set_register(&mut self, i: usize, value: u32) {
+        if self.exps.len() <= i {
+            self.exps.resize(i + 1, 0);
+        }
+        self.exps[i] = value;
+        trim_trailing_zeros(&mut self.exps);
+    }
+
+    /// Returns this number with register `i` increased by `by`, which is
+    /// multiplying by the `i`th prime to the power `by`. Taking and returning
+    /// `self` lets register changes be chained, as in
+    /// `state.inc_register(0, 3).dec_register(1, 2)?`. Panics if the register
+    /// would overflow a `u32`.
+    pub fn inc_register(mut self, i: usize, by: u32) -> PrimeBasis {
+        match self.register(i).checked_add(by) {
+            Some(value) => self.set_register(i, value),
+            None => panic!("Register {} overflowed adding {} to {}", i, by, self),
+        }
+        self
+    }
+
+    /// Returns this number with register `i` decreased by `by`, which is
+    /// dividing by the `i`th prime to the power `by`, or `None` if the register
+    /// is less than `by` and the result wouldn't be a natural number.
+    pub fn dec_register(mut self, i: usize, by: u32) -> Option<PrimeBasis> {
+        let value = self.register(i).checked_sub(by)?;
+        self.set_register(i, value);
+        Some(self)
+    }
+
+    /// Moves every register up by `offset`, so register `i` becomes register
+    /// `i + offset` and the first `offset` registers are 0. In terms of the
+    /// number, each prime factor is replaced by the prime `offset` places
+    /// later in `PRIMES`.
+    pub fn shift_registers(self, offset: usize) -> PrimeBasis {
+        if self.is_one() {
+            return self;
+        }
+        let mut exps = vec![0; offset];
+        exps.extend(self.exps);
+        PrimeBasis { exps }.normalized()
+    }
+
+    /// Returns this number with every register clamped to at most `cap`, as in
+    /// a machine whose registers can only count up to `cap`. In terms of the
+    /// number, each prime factor appears at most `cap` times.
+    pub fn with_register_cap(&self, cap: u32) -> PrimeBasis {
+        PrimeBasis {
+            exps: self.exps.iter().map(|&exp| exp.min(cap)).collect(),
+        }
+        .normalized()
+    }
+
+    /// Returns the nonzero registers as `(register_index, exponent)` pairs in
+    /// increasing order of index.
+    pub fn register_dump(&self) -> Vec<(usize, u32)> {
+        self.exps
+            .iter()
+            .enumerate()
+            .filter(|(_, &exp)| exp != 0)
+            .map(|(i, &exp)| (i, exp))
+            .collect()
+    }
+
+    /// Returns a wrapper that displays the nonzero registers in register-machine
+    /// notation, like `r0=3 r2=2` for 200. The number 1, which has every
+    /// register at 0, displays as the empty string.
+    pub fn display_registers(&self) -> RegisterDisplay<'_> {
+        RegisterDisplay(self)
+    }
+
+    /// Returns the number of registers that are nonzero: in other words, the
+    /// number of distinct prime factors.
+    pub fn num_nonzero_registers(&self) -> usize {
+        self.exps.iter().filter(|&&exp| exp != 0).count()
+    }
+
+    /// Returns the sum of every register: in other words, the number of prime
+    /// factors counted with multiplicity. This is a cheap measure of how
+    /// complex a state is that never overflows, unlike `value()`.
+    pub fn exponent_sum(&self) -> u64 {
+        self.exps.iter().map(|&exp| exp as u64).sum()
+    }
+
+    /// Returns the base-2 logarithm of this number, computed from the
+    /// exponents without ever multiplying anything out, so it never overflows.
+    /// The number needs a little more than this many bits: its exact bit length
+    /// is `floor` of this plus 1, up to floating-point error.
+    pub fn approx_bit_length(&self) -> f64 {
+        log2_of_exps(&self.exps)
+    }
+
+    /// Returns `Some(exp)` if this number is `p^exp`, where `p` is the prime
+    /// with index `prime_index`, and `None` if any other register is nonzero.
+    /// This is how FRACTRAN programs conventionally signal an output. Note
+    /// that 1 is `p^0` for every prime.
+    pub fn is_power_of_prime(&self, prime_index: usize) -> Option<u32> {
+        power_of_prime_in_exps(&self.exps, prime_index)
+    }
+
+    /// Returns `true` if this number is a power of two, mirroring
+    /// `u64::is_power_of_two`: like it, 1 counts as `2^0`.
+    pub fn is_power_of_two(&self) -> bool {
+        self.is_power_of_prime(0).is_some()
+    }
+
+    /// Returns `Some(exp)` if this number is `2^exp` and `None` otherwise. For
+    /// powers of two, this is the same as `u64::trailing_zeros`.
+    pub fn power_of_two_exp(&self) -> Option<u32> {
+        self.is_power_of_prime(0)
+    }
+
+    /// Returns the number of trailing zeros in the binary representation of
+    /// this number, mirroring `u64::trailing_zeros`: in other words, the
+    /// exponent of 2 in its factorization.
+    pub fn trailing_zeros(&self) -> u32 {
+        self.register(0)
+    }
+
+    /// Returns the same number with any trailing zero exponents removed, so that
+    /// equal numbers have equal representations. Every operation on
+    /// `PrimeBasis` returns normalized output, so this is only needed when
+    /// building `exps` by hand.
+    pub fn normalized(mut self) -> PrimeBasis {
+        trim_trailing_zeros(&mut self.exps);
+        self
+    }
+
+    /// Returns the exponents without any trailing zeros.
+    fn trimmed_exps(&self) -> &[u32] {
+        let len = self
+            .exps
+            .iter()
+            .rposition(|&exp| exp != 0)
+            .map_or(0, |i| i + 1);
+        &self.exps[..len]
+    }
+
+    /// Returns the number corresponding to this prime basis. This doesn't
+    /// check for overflow, so if the number doesn't fit in a `u64` it panics
+    /// in debug builds and silently wraps in release builds: use
+    /// `value_checked` if the number might be that large.
     pub fn value(&self) -> u64 {
+        self.value_in(&primes_for(&self.exps))
+    }
+
+    /// Returns the number corresponding to this prime basis, or `None` if it
+    /// doesn't fit in a `u64`, instead of overflowing like `value`. Registers
+    /// past `PRIMES` are multiplied out with the primes they stand for, so the
+    /// answer is never wrong, only missing.
+    pub fn value_checked(&self) -> Option<u64> {
+        checked_value_of_exps(&self.exps)
+    }
+
+    /// Returns `true` if this number is 1, meaning every register is 0. This
+    /// is the state most programs halt in, and unlike `value() == 1` it never
+    /// overflows.
+    pub fn is_one(&self) -> bool {
+        self.exps.iter().all(|&exp| exp == 0)
+    }
+
+    /// Compares this number to `n` without overflowing. The value is only
+    /// multiplied out until it passes `u64::MAX`, so comparing a huge state to
+    /// a small constant is cheap: any number too large for a `u64` is greater
+    /// than every `u64`.
+    pub fn cmp_u64(&self, n: u64) -> Ordering {
+        match self.value_checked() {
+            Some(value) => value.cmp(&n),
+            None => Ordering::Greater,
+        }
+    }
+
+    /// Returns the number corresponding to this prime basis, reading the
+    /// primes from `table` instead of the global `PRIMES`. The table must be
+    /// at least as long as `exps`.
+    pub fn value_in(&self, table: &PrimeTable) -> u64 {
+        assert!(
+            self.trimmed_exps().len() <= table.len(),
+            "{} registers used, but the prime table only has {}",
+            self.trimmed_exps().len(),
+            table.len()
+        );
         self.exps
             .iter()
-            .zip(&*PRIMES)
+            .zip(table)
             .fold(1, |acc, (&exp, p)| acc * p.pow(exp))
     }
-}
 
-impl std::fmt::Display for PrimeBasis {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let godel_str = self
-            .exps
+    /// Returns the exponents of the quotient `self / rhs`, or `None` if `rhs`
+    /// doesn't divide `self`. Dividing numbers subtracts their exponents.
+    fn quotient_exps(&self, rhs: &PrimeBasis) -> Option<Vec<u32>> {
+        self.exps
             .iter()
-            .zip(&*PRIMES)
-            .map(|(&exp, p)| {
-                if exp != 0 {
-                    format!("{}^{}", p, exp)
-                } else {
-                    String::new()
-                }
+            .zip_longest(&rhs.exps)
+            .map(|pair| match pair {
+                EitherOrBoth::Left(&a) => Some(a),
+                // if no value on the left side, the right must be 0
+                EitherOrBoth::Right(&0) => Some(0),
+                EitherOrBoth::Right(_) => None,
+                EitherOrBoth::Both(&a, &b) => a.checked_sub(b),
             })
-            .filter(|s| s != "")
-            .join(" ✕ ");
+            .collect()
+    }
+
+    /// Returns the quotient `self / rhs` if it's a natural number, and `None`
+    /// otherwise. Unlike `Div`, this never panics, and it checks divisibility
+    /// in the same pass as it divides.
+    pub fn checked_div(self, rhs: &PrimeBasis) -> Option<PrimeBasis> {
+        self.quotient_exps(rhs)
+            .map(|exps| PrimeBasis { exps }.normalized())
+    }
+
+    /// Adds the registers of `other` to the registers of this number, one by
+    /// one. This is exactly multiplication (`Mul`), named for the
+    /// register-machine view of FRACTRAN, where multiplying by a number adds
+    /// to registers.
+    pub fn add_registers(&self, other: &PrimeBasis) -> PrimeBasis {
+        self.clone() * other.clone()
+    }
+
+    /// Subtracts the registers of `other` from the registers of this number,
+    /// one by one, returning `None` if any register would go below 0. This is
+    /// exactly `checked_div`, named for the register-machine view of FRACTRAN,
+    /// where dividing by a number subtracts from registers.
+    pub fn sub_registers(&self, other: &PrimeBasis) -> Option<PrimeBasis> {
+        self.quotient_exps(other)
+            .map(|exps| PrimeBasis { exps }.normalized())
+    }
+
+    /// Subtracts the registers of `other` from the registers of this number,
+    /// one by one, clamping each register at 0 instead of failing. Unlike
+    /// `sub_registers` or division, this works even if `other` doesn't divide
+    /// this number, which models counter machines where decrementing an empty
+    /// counter does nothing.
+    pub fn saturating_sub_registers(&self, other: &PrimeBasis) -> PrimeBasis {
+        PrimeBasis {
+            exps: self
+                .exps
+                .iter()
+                .enumerate()
+                .map(|(i, &a)| a.saturating_sub(other.register(i)))
+                .collect(),
+        }
+        .normalized()
+    }
+
+    /// Returns the product of this number and `rhs`, or `None` if the product
+    /// goes over any of the bounds in `limit`, or has an exponent too large to
+    /// store. This lets a bounded interpreter refuse to produce states that
+    /// are too complex, instead of letting them grow without limit.
+    pub fn mul_checked(&self, rhs: &PrimeBasis, limit: &MulLimit) -> Option<PrimeBasis> {
+        let product = FractranNat::checked_mul(self, rhs)?;
+        if let Some(max_exponent) = limit.max_exponent {
+            if product.exps.iter().any(|&exp| exp > max_exponent) {
+                return None;
+            }
+        }
+        if let Some(max_bit_length) = limit.max_bit_length {
+            if product.approx_bit_length() > max_bit_length {
+                return None;
+            }
+        }
+        Some(product)
+    }
+
+    /// Returns this number raised to the power `exp`, which in the prime basis
+    /// just multiplies every exponent by `exp`. Panics if an exponent would
+    /// overflow a `u32`, in release builds too: use `checked_pow` if it might.
+    pub fn pow(&self, exp: u32) -> PrimeBasis {
+        match self.checked_pow(exp) {
+            Some(power) => power,
+            None => panic!("Exponent overflow raising {} to the power {}", self, exp),
+        }
+    }
+
+    /// Returns this number raised to the power `exp`, or `None` if an exponent
+    /// would overflow a `u32`, instead of panicking like `pow`.
+    pub fn checked_pow(&self, exp: u32) -> Option<PrimeBasis> {
+        let exps = self
+            .exps
+            .iter()
+            .map(|&a| a.checked_mul(exp))
+            .collect::<Option<Vec<u32>>>()?;
+        Some(PrimeBasis { exps }.normalized())
+    }
 
-        if godel_str == "" {
+    /// Returns the greatest common divisor of this number and `other`, which in
+    /// the prime basis is just the elementwise minimum of the exponents.
+    pub fn gcd(&self, other: &PrimeBasis) -> PrimeBasis {
+        PrimeBasis {
+            exps: self
+                .exps
+                .iter()
+                .zip(&other.exps)
+                .map(|(&a, &b)| a.min(b))
+                .collect(),
+        }
+        .normalized()
+    }
+
+    /// Returns the least common multiple of this number and `other`, which in
+    /// the prime basis is just the elementwise maximum of the exponents.
+    pub fn lcm(&self, other: &PrimeBasis) -> PrimeBasis {
+        PrimeBasis {
+            exps: self
+                .exps
+                .iter()
+                .zip_longest(&other.exps)
+                .map(|pair| *pair.reduce(|a, b| a.max(b)))
+                .collect(),
+        }
+        .normalized()
+    }
+}
+
+impl core::fmt::Display for PrimeBasis {
+    /// Writes the factored form, like `PrimeBasis(2^3 ✕ 5^2)`. With the
+    /// alternate flag (`{:#}`), writes the decimal value instead, like `200`,
+    /// falling back to the factored form if the value doesn't fit in a `u64`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if f.alternate() {
+            if let Some(value) = self.value_checked() {
+                return write!(f, "{}", value);
+            }
+        }
+
+        let godel_str = godel_string(&self.exps);
+
+        if godel_str.is_empty() {
             write!(f, "PrimeBasis(1)")
+        } else if f.alternate() {
+            write!(f, "PrimeBasis({}, too large for u64)", godel_str)
         } else {
             write!(f, "PrimeBasis({})", godel_str)
         }
     }
 }
 
+/// Displays the nonzero registers of a `PrimeBasis`, like `r0=3 r2=2`. Made by
+/// `PrimeBasis::display_registers`.
+#[derive(Copy, Clone, Debug)]
+pub struct RegisterDisplay<'a>(&'a PrimeBasis);
+
+impl core::fmt::Display for RegisterDisplay<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let regs = self
+            .0
+            .register_dump()
+            .into_iter()
+            .map(|(i, exp)| format!("r{}={}", i, exp))
+            .join(" ");
+        write!(f, "{}", regs)
+    }
+}
+
 impl Mul for PrimeBasis {
     type Output = PrimeBasis;
 
@@ -127,6 +647,24 @@ impl Mul for PrimeBasis {
                 .map(|pair| pair.reduce(|a, b| a + b))
                 .collect(),
         }
+        .normalized()
+    }
+}
+
+impl Product for PrimeBasis {
+    /// Multiplies every basis together by adding their exponents, giving 1 for
+    /// an empty iterator. There is deliberately no `Sum`: addition doesn't
+    /// correspond to anything simple on exponents, and would need every value
+    /// to be multiplied out, defeating the point of the prime basis.
+    fn product<I: Iterator<Item = PrimeBasis>>(iter: I) -> PrimeBasis {
+        iter.fold(PrimeBasis { exps: vec![] }, |acc, pb| acc * pb)
+    }
+}
+
+impl<'a> Product<&'a PrimeBasis> for PrimeBasis {
+    /// Multiplies every basis together, like the owned `Product`.
+    fn product<I: Iterator<Item = &'a PrimeBasis>>(iter: I) -> PrimeBasis {
+        iter.cloned().product()
     }
 }
 
@@ -137,17 +675,9 @@ impl Div for PrimeBasis {
     /// the input bases represent. Panics if the output would not be a natural
     /// number.
     fn div(self, rhs: Self) -> Self::Output {
-        if !rhs.divides(&self) {
-            panic!("Can't divide {} by {}", self, rhs);
-        } else {
-            PrimeBasis {
-                exps: self
-                    .exps
-                    .into_iter()
-                    .zip_longest(rhs.exps)
-                    .map(|pair| pair.reduce(|a, b| a - b))
-                    .collect(),
-            }
+        match self.quotient_exps(&rhs) {
+            Some(exps) => PrimeBasis { exps }.normalized(),
+            None => panic!("Can't divide {} by {}", self, rhs),
         }
     }
 }
@@ -168,6 +698,249 @@ impl Divides for PrimeBasis {
     }
 }
 
+lazy_static! {
+    /// The primes past `PRIMES` that have been needed so far, as a table of
+    /// the first primes that grows whenever a basis uses a register past its
+    /// end. Kept so that registers past `MAX_REGS` are only sieved for once.
+    static ref EXTENDED_PRIMES: RwLock<PrimeTable> = RwLock::new(PrimeTable::new(0));
+}
+
+/// A prime table borrowed from `PRIMES` or `EXTENDED_PRIMES`, from
+/// `primes_for`.
+pub(crate) enum PrimesFor {
+    Global(&'static PrimeTable),
+    Extended(RwLockReadGuard<'static, PrimeTable>),
+}
+
+impl Deref for PrimesFor {
+    type Target = PrimeTable;
+
+    fn deref(&self) -> &PrimeTable {
+        match self {
+            PrimesFor::Global(table) => table,
+            PrimesFor::Extended(table) => table,
+        }
+    }
+}
+
+/// Returns a table with a prime for every nonzero exponent in `exps`: the
+/// global `PRIMES` if it's long enough, which it almost always is, or else
+/// `EXTENDED_PRIMES`, grown first if needed. Zipping exponents with this
+/// instead of `PRIMES` means registers past `MAX_REGS`, which a basis built
+/// with a larger `PrimeTable` can have, are never silently dropped. The
+/// extended table stays read-locked while the result is alive, so don't call
+/// this again while holding one.
+pub(crate) fn primes_for(exps: &[u32]) -> PrimesFor {
+    let used = exps.iter().rposition(|&exp| exp != 0).map_or(0, |i| i + 1);
+    if used <= PRIMES.len() {
+        return PrimesFor::Global(&PRIMES);
+    }
+    let extended = EXTENDED_PRIMES.read();
+    if extended.len() >= used {
+        return PrimesFor::Extended(extended);
+    }
+    drop(extended);
+    let mut extended = EXTENDED_PRIMES.write();
+    // another thread may have grown it in the meantime, which `extend_to`
+    // allows for
+    extended.extend_to(used);
+    PrimesFor::Extended(extended.downgrade())
+}
+
+/// Computes the number with the given exponents, or `None` if it doesn't fit
+/// in a `u64`.
+pub(crate) fn checked_value_of_exps(exps: &[u32]) -> Option<u64> {
+    exps.iter()
+        .zip(primes_for(exps).iter())
+        .try_fold(1_u64, |acc, (&exp, p)| acc.checked_mul(p.checked_pow(exp)?))
+}
+
+/// Computes the number with the given exponents, or `None` if it doesn't fit
+/// in a `u128`.
+fn checked_u128_of_exps(exps: &[u32]) -> Option<u128> {
+    exps.iter()
+        .zip(primes_for(exps).iter())
+        .try_fold(1_u128, |acc, (&exp, &p)| {
+            acc.checked_mul((p as u128).checked_pow(exp)?)
+        })
+}
+
+/// Returns `Some(exp)` if the number with the given exponents is a power of the
+/// prime with index `prime_index`, and `None` if any other exponent is nonzero.
+pub(crate) fn power_of_prime_in_exps(exps: &[u32], prime_index: usize) -> Option<u32> {
+    let others_zero = exps
+        .iter()
+        .enumerate()
+        .all(|(i, &exp)| i == prime_index || exp == 0);
+    if others_zero {
+        Some(exps.get(prime_index).copied().unwrap_or(0))
+    } else {
+        None
+    }
+}
+
+/// Writes the factorization with the given exponents, like `2^3 ✕ 5^2`, leaving
+/// out primes with an exponent of 0. The number 1 gives the empty string.
+pub(crate) fn godel_string(exps: &[u32]) -> String {
+    exps.iter()
+        .zip(primes_for(exps).iter())
+        .filter(|(&exp, _)| exp != 0)
+        .map(|(&exp, p)| format!("{}^{}", p, exp))
+        .join(" ✕ ")
+}
+
+/// Computes the base-2 logarithm of the number with the given exponents. This
+/// is only approximate, but it never overflows.
+fn log2_of_exps(exps: &[u32]) -> f64 {
+    exps.iter()
+        .zip(primes_for(exps).iter())
+        .map(|(&exp, &p)| exp as f64 * math::log2(p as f64))
+        .sum()
+}
+
+/// Computes the exact number with the given exponents as a list of base-2^32
+/// digits, least significant first. This can be very slow for large numbers,
+/// so it's only used when the cheaper methods can't decide.
+fn big_value_of_exps(exps: &[u32]) -> Vec<u32> {
+    let mut digits = vec![1_u32];
+    for (&exp, &p) in exps.iter().zip(primes_for(exps).iter()) {
+        for _ in 0..exp {
+            let mut carry = 0_u64;
+            for digit in digits.iter_mut() {
+                let prod = *digit as u64 * p + carry;
+                *digit = prod as u32;
+                carry = prod >> 32;
+            }
+            if carry != 0 {
+                digits.push(carry as u32);
+            }
+        }
+    }
+    digits
+}
+
+impl PartialEq for PrimeBasis {
+    fn eq(&self, other: &Self) -> bool {
+        self.trimmed_exps() == other.trimmed_exps()
+    }
+}
+
+impl Eq for PrimeBasis {}
+
+impl Hash for PrimeBasis {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // must agree with `eq`, so trailing zeros can't affect the hash
+        self.trimmed_exps().hash(state);
+    }
+}
+
+impl PartialOrd for PrimeBasis {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PrimeBasis {
+    /// Compares the numbers that the bases represent, without ever computing
+    /// them in a way that could overflow. Common factors are cancelled first,
+    /// dividing both numbers by their gcd, which leaves two coprime numbers
+    /// that are only equal if both are 1. Those are compared exactly if they
+    /// fit in a `u128`, and otherwise by their logarithms. Only if the
+    /// logarithms are too close to call, which takes numbers well over 2^128
+    /// that agree in their first nine or so digits, is the exact value
+    /// computed, which takes time proportional to the square of the number of
+    /// digits.
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (lhs, rhs): (Vec<u32>, Vec<u32>) = self
+            .exps
+            .iter()
+            .zip_longest(&other.exps)
+            .map(|pair| match pair {
+                EitherOrBoth::Left(&a) => (a, 0),
+                EitherOrBoth::Right(&b) => (0, b),
+                EitherOrBoth::Both(&a, &b) => (a - a.min(b), b - a.min(b)),
+            })
+            .unzip();
+
+        if lhs == rhs {
+            // only possible if both are now all zero, so they're equal
+            return Ordering::Equal;
+        }
+
+        if let (Some(small_lhs), Some(small_rhs)) =
+            (checked_u128_of_exps(&lhs), checked_u128_of_exps(&rhs))
+        {
+            return small_lhs.cmp(&small_rhs);
+        }
+
+        let (log_lhs, log_rhs) = (log2_of_exps(&lhs), log2_of_exps(&rhs));
+        let tolerance = 1e-9 * (1.0 + log_lhs.max(log_rhs));
+        let diff = log_lhs - log_rhs;
+        if diff > tolerance {
+            Ordering::Greater
+        } else if diff < -tolerance {
+            Ordering::Less
+        } else {
+            let (big_lhs, big_rhs) = (big_value_of_exps(&lhs), big_value_of_exps(&rhs));
+            big_lhs
+                .len()
+                .cmp(&big_rhs.len())
+                .then_with(|| big_lhs.iter().rev().cmp(big_rhs.iter().rev()))
+        }
+    }
+}
+
+impl FractranNat for PrimeBasis {
+    fn to_u64_checked(&self) -> Option<u64> {
+        self.value_checked()
+    }
+
+    fn is_zero(&self) -> bool {
+        // zero has no prime factorization, so it can't be represented
+        false
+    }
+
+    fn is_one(&self) -> bool {
+        PrimeBasis::is_one(self)
+    }
+
+    fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+        // the only way to overflow is for an exponent to overflow
+        let exps = self
+            .exps
+            .iter()
+            .zip_longest(&rhs.exps)
+            .map(|pair| match pair {
+                EitherOrBoth::Both(&a, &b) => a.checked_add(b),
+                EitherOrBoth::Left(&a) | EitherOrBoth::Right(&a) => Some(a),
+            })
+            .collect::<Option<Vec<u32>>>()?;
+        Some(PrimeBasis { exps }.normalized())
+    }
+
+    fn divides_product(&self, lhs: &Self, rhs: &Self) -> bool {
+        // each exponent just has to be at most the sum of the other two, so
+        // there's no need to build the product
+        self.exps
+            .iter()
+            .enumerate()
+            .all(|(i, &exp)| exp as u64 <= lhs.register(i) as u64 + rhs.register(i) as u64)
+    }
+
+    fn gcd(&self, other: &Self) -> Self {
+        PrimeBasis::gcd(self, other)
+    }
+
+    fn is_power_of(&self, prime: u64) -> Option<u32> {
+        match primes_for(&self.exps).binary_search(&prime) {
+            Ok(index) => self.is_power_of_prime(index),
+            // not a prime we can store, so only 1 is a power of it
+            Err(_) if prime >= 2 && self.is_one() => Some(0),
+            Err(_) => None,
+        }
+    }
+}
+
 impl TryFrom<u64> for PrimeBasis {
     type Error = Error;
 
@@ -176,6 +949,23 @@ impl TryFrom<u64> for PrimeBasis {
     }
 }
 
+impl From<Vec<u32>> for PrimeBasis {
+    /// Uses the given exponents directly, so `vec![3, 0, 2]` is 2^3 ✕ 5^2,
+    /// trimming any trailing zeros. This never overflows, unlike going through
+    /// a `u64`.
+    fn from(exps: Vec<u32>) -> PrimeBasis {
+        PrimeBasis { exps }.normalized()
+    }
+}
+
+impl From<&[u32]> for PrimeBasis {
+    /// Copies the given exponents, like `From<Vec<u32>>`.
+    fn from(exps: &[u32]) -> PrimeBasis {
+        PrimeBasis::from(exps.to_vec())
+    }
+}
+
+#[allow(clippy::from_over_into)]
 impl Into<u64> for PrimeBasis {
     /// Returns the natural number that is represented by this prime basis.
     fn into(self) -> u64 {
@@ -202,6 +992,238 @@ mod tests {
         assert_eq!(PrimeBasis::try_new(0), Err(Error::NumIsZero));
     }
 
+    #[test]
+    fn test_from_factors() {
+        assert_eq!(
+            PrimeBasis::from_factors(&[(2, 10), (3, 5), (5, 3)])
+                .unwrap()
+                .value(),
+            2_u64.pow(10) * 3_u64.pow(5) * 5_u64.pow(3)
+        );
+        assert_eq!(PrimeBasis::from_factors(&[(5, 2), (2, 3)]), Ok(new(200)));
+        assert_eq!(PrimeBasis::from_factors(&[(2, 1), (2, 2)]), Ok(new(8)));
+        assert_eq!(
+            PrimeBasis::from_factors(&[(2, u32::MAX), (3, 1), (2, 1)]),
+            Err(Error::ExponentOverflow(2))
+        );
+        assert_eq!(
+            PrimeBasis::from_factors(&[(2, u32::MAX), (2, 0)])
+                .unwrap()
+                .exps,
+            vec![u32::MAX]
+        );
+        assert_eq!(PrimeBasis::from_factors(&[]), Ok(new(1)));
+        assert_eq!(PrimeBasis::from_factors(&[(7, 0)]), Ok(new(1)));
+        // far too big for u64
+        assert_eq!(
+            PrimeBasis::from_factors(&[(7, 1000)]).unwrap().exps,
+            vec![0, 0, 0, 1000]
+        );
+
+        assert_eq!(
+            PrimeBasis::from_factors(&[(2, 1), (9, 1)]),
+            Err(Error::NotPrime(9))
+        );
+        assert_eq!(PrimeBasis::from_factors(&[(1, 1)]), Err(Error::NotPrime(1)));
+        assert_eq!(
+            PrimeBasis::from_factors(&[(7927, 1)]),
+            Err(Error::RegisterOverflow(7927))
+        );
+    }
+
+    #[test]
+    fn test_primorial() {
+        assert_eq!(PrimeBasis::primorial(0), Ok(new(1)));
+        assert_eq!(PrimeBasis::primorial(5), Ok(new(2 * 3 * 5 * 7 * 11)));
+        assert_eq!(PrimeBasis::primorial(1000).unwrap().exps, vec![1; 1000]);
+        assert_eq!(
+            PrimeBasis::primorial(1001),
+            Err(Error::RegisterOverflow(1001))
+        );
+    }
+
+    #[test]
+    fn test_factorial() {
+        let mut fact = 1;
+        for n in 0..=20 {
+            if n > 0 {
+                fact *= n;
+            }
+            assert_eq!(PrimeBasis::factorial(n), Ok(new(fact)));
+        }
+        // 100! has 97 factors of 2 and 48 factors of 3
+        let big = PrimeBasis::factorial(100).unwrap();
+        assert_eq!(big.register(0), 97);
+        assert_eq!(big.register(1), 48);
+        assert_eq!(big.exps.len(), 25);
+
+        assert!(PrimeBasis::factorial(7919).is_ok());
+        assert_eq!(
+            PrimeBasis::factorial(7920),
+            Err(Error::RegisterOverflow(7920))
+        );
+    }
+
+    #[test]
+    fn test_product() {
+        let bases = vec![new(12), new(1), new(35), new(2)];
+        assert_eq!(bases.iter().product::<PrimeBasis>(), new(12 * 35 * 2));
+        assert_eq!(bases.into_iter().product::<PrimeBasis>(), new(12 * 35 * 2));
+        assert_eq!(
+            Vec::<PrimeBasis>::new().into_iter().product::<PrimeBasis>(),
+            new(1)
+        );
+        // factors that would overflow a u64 if multiplied out
+        let big: PrimeBasis = (0..10).map(|_| new(1 << 10)).product();
+        assert_eq!(big.exps, vec![100]);
+    }
+
+    #[test]
+    fn test_value_checked() {
+        assert_eq!(new(200).value_checked(), Some(200));
+        assert_eq!(new(1).value_checked(), Some(1));
+        assert_eq!(PrimeBasis::from(vec![63]).value_checked(), Some(1 << 63));
+        assert_eq!(PrimeBasis::from(vec![64]).value_checked(), None);
+        // each factor fits, but the product doesn't
+        assert_eq!(PrimeBasis::from(vec![40, 20]).value_checked(), None);
+        assert_eq!(PrimeBasis::from(vec![0, 0, 0, 1000]).value_checked(), None);
+
+        // registers past `PRIMES` are multiplied out with the right prime, not
+        // dropped
+        let p = PrimeTable::new(1051)[1050];
+        let mut past = new(1);
+        past.set_register(1050, 4);
+        assert_eq!(past.value_checked(), Some(p.pow(4)));
+        past.set_register(1050, 5);
+        assert_eq!(past.value_checked(), None);
+        assert_eq!(past.cmp_u64(u64::MAX), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_is_one() {
+        assert!(new(1).is_one());
+        assert!(PrimeBasis { exps: vec![0, 0] }.is_one());
+        assert!(!new(2).is_one());
+        assert!(!PrimeBasis::from(vec![0, 0, 0, 1000]).is_one());
+        assert!(FractranNat::is_one(&new(1)));
+        assert!(FractranNat::is_one(&1_u64));
+        assert!(!FractranNat::is_one(&7_u8));
+    }
+
+    #[test]
+    fn test_cmp_u64() {
+        assert_eq!(new(1).cmp_u64(1), Ordering::Equal);
+        assert_eq!(new(1).cmp_u64(2), Ordering::Less);
+        assert_eq!(new(200).cmp_u64(199), Ordering::Greater);
+        assert_eq!(PrimeBasis::from(vec![63]).cmp_u64(1 << 63), Ordering::Equal);
+        assert_eq!(
+            PrimeBasis::from(vec![64]).cmp_u64(u64::MAX),
+            Ordering::Greater
+        );
+        assert_eq!(
+            PrimeBasis::from(vec![0, 0, 0, 1000]).cmp_u64(2),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_from_godel_str() {
+        assert_eq!(PrimeBasis::from_godel_str("2^3 * 3^0 * 5^2"), Ok(new(200)));
+        assert_eq!(PrimeBasis::from_godel_str("5^2*2^3"), Ok(new(200)));
+        assert_eq!(PrimeBasis::from_godel_str("2 ✕ 7"), Ok(new(14)));
+        assert_eq!(PrimeBasis::from_godel_str(" 1 "), Ok(new(1)));
+        assert_eq!(
+            PrimeBasis::from_godel_str("7^1000").unwrap().exps,
+            vec![0, 0, 0, 1000]
+        );
+
+        // round trip through Display
+        for pb in [new(1), new(200), PrimeBasis::from(vec![0, 64, 0, 1])] {
+            assert_eq!(PrimeBasis::from_godel_str(&pb.to_string()), Ok(pb));
+        }
+
+        assert_eq!(
+            PrimeBasis::from_godel_str("2^3 * 4^2"),
+            Err(Error::NotPrime(4))
+        );
+        assert_eq!(PrimeBasis::from_godel_str("0^2"), Err(Error::NumIsZero));
+        assert_eq!(
+            PrimeBasis::from_godel_str("2^-1"),
+            Err(Error::Malformed("2^-1".to_string()))
+        );
+        assert_eq!(
+            PrimeBasis::from_godel_str("2^3 *"),
+            Err(Error::Malformed("2^3 *".to_string()))
+        );
+        assert_eq!(
+            PrimeBasis::from_godel_str(""),
+            Err(Error::Malformed(String::new()))
+        );
+    }
+
+    #[test]
+    fn test_shift_registers() {
+        // 200 = 2^3 ✕ 5^2 becomes 5^3 ✕ 11^2
+        assert_eq!(new(200).shift_registers(2), new(125 * 121));
+        assert_eq!(new(200).shift_registers(0), new(200));
+        assert_eq!(new(1).shift_registers(5), new(1));
+    }
+
+    #[test]
+    fn test_with_register_cap() {
+        // 200 = 2^3 ✕ 5^2
+        assert_eq!(new(200).with_register_cap(2), new(4 * 25));
+        assert_eq!(new(200).with_register_cap(3), new(200));
+        assert_eq!(new(200).with_register_cap(0), new(1));
+        assert_eq!(new(1).with_register_cap(5), new(1));
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut pb = new(200);
+        assert_eq!(pb.register(0), 3);
+        assert_eq!(pb.register(1), 0);
+        assert_eq!(pb.register(2), 2);
+        assert_eq!(pb.register(100), 0);
+
+        pb.set_register(4, 1);
+        assert_eq!(pb.value(), 200 * 11);
+        pb.set_register(4, 0);
+        assert_eq!(pb.exps, vec![3, 0, 2]);
+        pb.set_register(2, 0);
+        assert_eq!(pb.exps, vec![3]);
+        pb.set_register(0, 0);
+        assert_eq!(pb, new(1));
+    }
+
+    #[test]
+    fn test_inc_dec_register() {
+        // 200 = 2^3 5^2
+        assert_eq!(new(200).inc_register(1, 2), new(200 * 9));
+        assert_eq!(new(200).inc_register(0, 0), new(200));
+        assert_eq!(new(1).inc_register(4, 1), new(11));
+        assert_eq!(new(200).dec_register(2, 2), Some(new(8)));
+        assert_eq!(new(200).dec_register(2, 3), None);
+        assert_eq!(new(200).dec_register(1, 1), None);
+        assert_eq!(new(200).dec_register(7, 0), Some(new(200)));
+
+        let chained = || {
+            new(200)
+                .inc_register(0, 3)
+                .dec_register(2, 1)?
+                .dec_register(0, 6)
+        };
+        assert_eq!(chained(), Some(new(5)));
+        // registers are stored without trailing zeros, however they're reached
+        assert_eq!(new(11).dec_register(4, 1).unwrap().exps, Vec::<u32>::new());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_inc_register_overflow() {
+        let _ = new(2).inc_register(0, u32::MAX);
+    }
+
     #[test]
     fn test_tryfrom_u64() {
         let nums: Vec<u64> = vec![1, 2, 3, 5, 10, 20, 60, 2520, 70000];
@@ -235,6 +1257,157 @@ mod tests {
     }
 
     #[test]
+    fn test_ord() {
+        let mut nums: Vec<u64> = vec![1, 2520, 7, 64, 63, 65, 1000, 999, 2, 3, 1024];
+        let mut bases: Vec<PrimeBasis> = nums.iter().map(|&n| new(n)).collect();
+        nums.sort();
+        bases.sort();
+        let sorted: Vec<u64> = bases.into_iter().map(|pb| pb.value()).collect();
+        assert_eq!(sorted, nums);
+
+        assert_eq!(new(12).cmp(&new(12)), Ordering::Equal);
+        assert!(new(6) < new(8));
+        assert!(new(9) > new(8));
+    }
+
+    #[test]
+    fn test_ord_beyond_u64() {
+        // 3^100 vs 2^158: both overflow u64, and 3^100 is slightly larger
+        let big3 = PrimeBasis { exps: vec![0, 100] };
+        let big2 = PrimeBasis { exps: vec![158] };
+        assert!(big3 > big2);
+        assert!(PrimeBasis { exps: vec![159] } > big3);
+
+        // 3^80 and 2^127 both fit in a u128 and are compared exactly
+        assert!(PrimeBasis { exps: vec![0, 80] } < PrimeBasis { exps: vec![127] });
+
+        // a huge common factor cancels, leaving 5 against 7
+        let common = PrimeBasis::from(vec![1000, 1000]);
+        let lhs = common.clone() * new(5);
+        let rhs = common * new(7);
+        assert!(lhs < rhs);
+        assert_eq!(lhs.cmp(&lhs.clone()), Ordering::Equal);
+
+        // trailing zeros don't affect the ordering
+        assert_eq!(
+            PrimeBasis { exps: vec![1, 0] }.cmp(&PrimeBasis { exps: vec![1] }),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn test_pow() {
+        for num in [1, 2, 3, 6, 10, 12, 30] {
+            for k in 0..=8 {
+                assert_eq!(new(num).pow(k).value(), num.pow(k));
+            }
+        }
+        // 2^100 is too big for u64, but fine as a PrimeBasis
+        assert_eq!(new(8).pow(100).exps, vec![300]);
+
+        assert_eq!(new(12).checked_pow(3), Some(new(1728)));
+        assert_eq!(new(1).checked_pow(u32::MAX), Some(new(1)));
+        assert_eq!(new(12).checked_pow(u32::MAX), None);
+        assert_eq!(PrimeBasis::from(vec![1 << 16]).checked_pow(1 << 16), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_pow_overflow() {
+        let _ = new(4).pow(1 << 31);
+    }
+
+    #[test]
+    fn test_gcd_lcm() {
+        fn naive_gcd(a: u64, b: u64) -> u64 {
+            if b == 0 {
+                a
+            } else {
+                naive_gcd(b, a % b)
+            }
+        }
+
+        for a in 1..=60 {
+            for b in 1..=60 {
+                let (pb1, pb2) = (new(a), new(b));
+                let gcd = naive_gcd(a, b);
+                assert_eq!(pb1.gcd(&pb2).value(), gcd);
+                assert_eq!(pb1.lcm(&pb2).value(), a * b / gcd);
+            }
+        }
+    }
+
+    #[test]
+    fn test_checked_div() {
+        assert_eq!(new(28).checked_div(&new(7)).unwrap().value(), 4);
+        assert_eq!(new(40).checked_div(&new(40)).unwrap().value(), 1);
+        assert_eq!(new(7).checked_div(&new(70)), None);
+        assert_eq!(new(250).checked_div(&new(100)), None);
+        assert_eq!(new(1).checked_div(&new(2)), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_div_panics() {
+        let _ = new(7) / new(2);
+    }
+
+    #[test]
+    fn test_normalized() {
+        let padded = PrimeBasis {
+            exps: vec![3, 0, 0],
+        };
+        assert_eq!(padded.normalized().exps, vec![3]);
+        assert_eq!(PrimeBasis { exps: vec![0, 0] }.normalized(), new(1));
+        assert_eq!(new(6) / new(3), new(2));
+        assert_eq!(new(6).checked_div(&new(6)), Some(new(1)));
+        assert_eq!(new(20).gcd(&new(3)), new(1));
+        assert_eq!(new(12).pow(0), new(1));
+        assert_eq!(
+            PrimeBasis { exps: vec![1, 0] } * PrimeBasis { exps: vec![1] },
+            new(4)
+        );
+    }
+
+    #[test]
+    fn test_eq_ignores_trailing_zeros() {
+        use std::collections::HashSet;
+
+        let short = PrimeBasis { exps: vec![1] };
+        let long = PrimeBasis { exps: vec![1, 0] };
+        assert_eq!(short, long);
+        assert_eq!(PrimeBasis { exps: vec![0, 0] }, new(1));
+        assert_ne!(
+            PrimeBasis {
+                exps: vec![1, 0, 1]
+            },
+            short
+        );
+
+        let set: HashSet<PrimeBasis> = vec![short, long, new(2)].into_iter().collect();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_divides_product() {
+        for a in 1..=30 {
+            for b in 1..=30 {
+                for d in [1, 2, 4, 6, 9, 12, 35, 100] {
+                    assert_eq!(
+                        new(d).divides_product(&new(a), &new(b)),
+                        (a * b) % d == 0,
+                        "{} | {} * {}",
+                        d,
+                        a,
+                        b
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_divides() {
         let help_div = |a, b| {
             let pb1 = new(a);
@@ -250,4 +1423,262 @@ mod tests {
         assert_eq!(help_div(2, 7), false);
         assert_eq!(help_div(100, 250), false);
     }
+
+    #[test]
+    fn test_is_power_of_prime() {
+        assert_eq!(new(32).is_power_of_prime(0), Some(5));
+        assert_eq!(new(32).is_power_of_prime(1), None);
+        assert_eq!(new(343).is_power_of_prime(3), Some(3));
+        assert_eq!(new(1).is_power_of_prime(0), Some(0));
+        assert_eq!(new(1).is_power_of_prime(7), Some(0));
+        assert_eq!(new(12).is_power_of_prime(0), None);
+        // trailing zeros don't matter
+        let pb = PrimeBasis {
+            exps: vec![0, 2, 0, 0],
+        };
+        assert_eq!(pb.is_power_of_prime(1), Some(2));
+    }
+
+    #[test]
+    fn test_power_of_two() {
+        for num in 1..=1024_u64 {
+            let pb = new(num);
+            assert_eq!(pb.is_power_of_two(), num.is_power_of_two());
+            assert_eq!(pb.trailing_zeros(), num.trailing_zeros());
+            let expected = if num.is_power_of_two() {
+                Some(num.trailing_zeros())
+            } else {
+                None
+            };
+            assert_eq!(pb.power_of_two_exp(), expected);
+        }
+    }
+
+    #[test]
+    fn test_is_power_of() {
+        for num in 1..=1000_u64 {
+            for &prime in &[2, 3, 5, 7, 7927] {
+                assert_eq!(new(num).is_power_of(prime), num.is_power_of(prime));
+            }
+        }
+        assert_eq!(new(6).is_power_of(6), None);
+        assert_eq!(new(1).is_power_of(1), None);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(new(200).to_string(), "PrimeBasis(2^3 ✕ 5^2)");
+        assert_eq!(new(1).to_string(), "PrimeBasis(1)");
+        assert_eq!(format!("{:#}", new(200)), "200");
+        assert_eq!(format!("{:#}", new(1)), "1");
+        assert_eq!(
+            format!("{:#}", new(2).pow(64)),
+            "PrimeBasis(2^64, too large for u64)"
+        );
+    }
+
+    #[test]
+    fn test_register_dump() {
+        assert_eq!(new(200).register_dump(), vec![(0, 3), (2, 2)]);
+        assert_eq!(new(1).register_dump(), vec![]);
+        assert_eq!(new(200).display_registers().to_string(), "r0=3 r2=2");
+        assert_eq!(new(7).display_registers().to_string(), "r3=1");
+        assert_eq!(new(1).display_registers().to_string(), "");
+    }
+
+    #[test]
+    fn test_add_sub_registers() {
+        // r0=3 r2=2 plus r0=1 r1=1
+        let sum = new(200).add_registers(&new(6));
+        assert_eq!(sum.register_dump(), vec![(0, 4), (1, 1), (2, 2)]);
+        assert_eq!(sum, new(1200));
+        assert_eq!(sum.sub_registers(&new(6)), Some(new(200)));
+        assert_eq!(new(200).sub_registers(&new(200)), Some(new(1)));
+        // r1 would go negative
+        assert_eq!(new(200).sub_registers(&new(6)), None);
+    }
+
+    #[test]
+    fn test_from_exps() {
+        assert_eq!(PrimeBasis::from(vec![3, 0, 2]), new(200));
+        assert_eq!(PrimeBasis::from(vec![3, 0, 2, 0, 0]).exps, vec![3, 0, 2]);
+        assert_eq!(PrimeBasis::from(&[0, 0][..]).exps, vec![]);
+        assert_eq!(PrimeBasis::from(&[1, 1][..]), new(6));
+        // far too large for a u64, but fine as exponents
+        assert_eq!(PrimeBasis::from(vec![1000]), new(2).pow(1000));
+    }
+
+    #[test]
+    fn test_saturating_sub_registers() {
+        // r0=3 r2=2 minus r0=1 r1=1 clamps r1 at 0
+        assert_eq!(new(200).saturating_sub_registers(&new(6)), new(100));
+        assert_eq!(new(6).saturating_sub_registers(&new(200)), new(3));
+        assert_eq!(new(200).saturating_sub_registers(&new(200)), new(1));
+        assert_eq!(new(1).saturating_sub_registers(&new(30)), new(1));
+        assert_eq!(
+            new(1200).saturating_sub_registers(&new(6)),
+            new(1200).sub_registers(&new(6)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_size_metrics() {
+        assert_eq!(new(200).num_nonzero_registers(), 2);
+        assert_eq!(new(200).exponent_sum(), 5);
+        assert_eq!(new(1).num_nonzero_registers(), 0);
+        assert_eq!(new(1).exponent_sum(), 0);
+        let huge = PrimeBasis::from(vec![u32::MAX, 0, u32::MAX]);
+        assert_eq!(huge.num_nonzero_registers(), 2);
+        assert_eq!(huge.exponent_sum(), 2 * u32::MAX as u64);
+    }
+
+    #[test]
+    fn test_approx_bit_length() {
+        assert_eq!(new(1).approx_bit_length(), 0.0);
+        assert_eq!(new(1024).approx_bit_length(), 10.0);
+        assert!((new(200).approx_bit_length() - 200_f64.log2()).abs() < 1e-9);
+        for num in 1..1000_u64 {
+            let bits = 64 - num.leading_zeros();
+            assert_eq!(new(num).approx_bit_length().floor() as u32 + 1, bits);
+        }
+        // far too large to compute the value
+        assert_eq!(
+            PrimeBasis::from(vec![100_000]).approx_bit_length(),
+            100_000.0
+        );
+    }
+
+    #[test]
+    fn test_factor_map() {
+        let map: BTreeMap<u64, u32> = vec![(2, 3), (5, 2)].into_iter().collect();
+        assert_eq!(new(200).to_factor_map(), map);
+        assert_eq!(PrimeBasis::from_factor_map(&map), Ok(new(200)));
+        assert_eq!(new(1).to_factor_map(), BTreeMap::new());
+        assert_eq!(PrimeBasis::from_factor_map(&BTreeMap::new()), Ok(new(1)));
+
+        let with_zero: BTreeMap<u64, u32> = vec![(3, 0), (7, 1)].into_iter().collect();
+        assert_eq!(PrimeBasis::from_factor_map(&with_zero), Ok(new(7)));
+        let not_prime: BTreeMap<u64, u32> = vec![(2, 1), (9, 1)].into_iter().collect();
+        assert_eq!(
+            PrimeBasis::from_factor_map(&not_prime),
+            Err(Error::NotPrime(9))
+        );
+        let too_big: BTreeMap<u64, u32> = vec![(7927, 1)].into_iter().collect();
+        assert_eq!(
+            PrimeBasis::from_factor_map(&too_big),
+            Err(Error::RegisterOverflow(7927))
+        );
+
+        for num in 1..500 {
+            assert_eq!(
+                PrimeBasis::from_factor_map(&new(num).to_factor_map()),
+                Ok(new(num))
+            );
+        }
+    }
+
+    #[test]
+    fn test_mul_checked() {
+        let no_limit = MulLimit::default();
+        assert_eq!(new(200).mul_checked(&new(6), &no_limit), Some(new(1200)));
+        let huge = PrimeBasis::from(vec![u32::MAX]);
+        assert_eq!(huge.mul_checked(&new(2), &no_limit), None);
+
+        let exp_limit = MulLimit {
+            max_exponent: Some(3),
+            ..MulLimit::default()
+        };
+        assert_eq!(new(4).mul_checked(&new(2), &exp_limit), Some(new(8)));
+        assert_eq!(new(8).mul_checked(&new(6), &exp_limit), None);
+
+        let bit_limit = MulLimit {
+            max_bit_length: Some(10.0),
+            ..MulLimit::default()
+        };
+        assert_eq!(new(32).mul_checked(&new(32), &bit_limit), Some(new(1024)));
+        assert_eq!(new(32).mul_checked(&new(33), &bit_limit), None);
+    }
+
+    #[test]
+    fn test_prime_tables() {
+        // the largest prime in the table should still be representable
+        let largest = *PRIMES.last().unwrap();
+        assert_eq!(new(largest).register(PRIMES.len() - 1), 1);
+
+        let small = PrimeTable::new(3);
+        assert_eq!(PrimeBasis::try_new_in(200, &small), Ok(new(200)));
+        assert_eq!(
+            PrimeBasis::try_new_in(14, &small),
+            Err(Error::RegisterOverflow(14))
+        );
+        assert_eq!(
+            PrimeBasis::from_factors_in(&[(7, 1)], &small),
+            Err(Error::RegisterOverflow(7))
+        );
+        // 7921 = 89^2 is past the end of a 20-prime table, but isn't prime
+        let twenty = PrimeTable::new(20);
+        assert_eq!(
+            PrimeBasis::from_factors_in(&[(7921, 1)], &twenty),
+            Err(Error::NotPrime(7921))
+        );
+        assert_eq!(
+            PrimeBasis::from_factors_in(&[(89, 1)], &twenty),
+            Err(Error::RegisterOverflow(89))
+        );
+
+        // 7927 is the first prime past the global table
+        assert_eq!(
+            PrimeBasis::try_new(2 * 7927),
+            Err(Error::RegisterOverflow(2 * 7927))
+        );
+        let mut big = PrimeTable::default();
+        big.extend_up_to(7927);
+        let pb = PrimeBasis::try_new_in(2 * 7927, &big).unwrap();
+        assert_eq!(pb.register(PRIMES.len()), 1);
+        assert_eq!(pb.value_in(&big), 2 * 7927);
+        assert_eq!(
+            PrimeBasis::from_factors_in(&[(2, 1), (7927, 1)], &big),
+            Ok(pb)
+        );
+    }
+
+    #[test]
+    fn test_registers_past_max_regs() {
+        // registers past the global table still count as the right primes
+        let table = PrimeTable::new(1100);
+        let p = table[1050];
+        let pb = PrimeBasis::try_new_in(p, &table).unwrap();
+        let one = new(1);
+        assert_ne!(pb, one);
+        assert_eq!(pb.cmp(&one), Ordering::Greater);
+        assert_eq!(pb.cmp(&new(2).pow(13)), Ordering::Greater);
+        assert_eq!(pb.cmp(&new(2).pow(14)), Ordering::Less);
+        assert_eq!(pb.value(), p);
+        assert_eq!(pb.to_u64_checked(), Some(p));
+        assert_eq!(pb.to_string(), format!("PrimeBasis({}^1)", p));
+        assert_eq!(pb.to_factor_map(), vec![(p, 1)].into_iter().collect());
+        assert_eq!(pb.is_power_of(p), Some(1));
+        assert!(pb.approx_bit_length() > 13.0);
+
+        let q = table[1099];
+        let pq = PrimeBasis::try_new_in(p * q, &table).unwrap();
+        let qq = PrimeBasis::try_new_in(q * q, &table).unwrap();
+        assert_eq!(pq.cmp(&qq), Ordering::Less);
+        // `Display` round-trips through the table the basis was built with
+        assert_eq!(
+            PrimeBasis::from_godel_str_in(&pq.to_string(), &table),
+            Ok(pq)
+        );
+
+        // sorting many such states only sieves for the extra primes once
+        let mut states: Vec<PrimeBasis> = (1000..1100)
+            .rev()
+            .map(|i| PrimeBasis::try_new_in(table[i], &table).unwrap())
+            .collect();
+        states.sort();
+        assert!(states
+            .windows(2)
+            .all(|pair| pair[0].value() < pair[1].value()));
+        assert!(EXTENDED_PRIMES.read().len() >= 1100);
+    }
 }
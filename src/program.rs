@@ -2,7 +2,76 @@
 //! fractions.
 
 use super::frac::{Fraction, FractranNat, StepResult};
-use std::iter::Iterator;
+use super::primebasis::{self, Divides, PrimeBasis};
+use super::PRIMES;
+use alloc::collections::BTreeSet;
+use alloc::{vec, vec::Vec};
+use core::convert::TryFrom;
+use core::fmt;
+use core::hash::Hash;
+use core::iter::{FusedIterator, Iterator};
+use core::ops::Add;
+use core::time::Duration;
+use itertools::Itertools;
+#[cfg(feature = "std")]
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ProgramError {
+    #[error("Cannot create a program with no fractions")]
+    EmptyProgram,
+
+    #[error("Instruction {instruction} jumps to {target}, which doesn't exist")]
+    InvalidJumpTarget { instruction: usize, target: usize },
+
+    #[error("Fraction {fraction} uses register {register}, but there are only {} primes",
+            PRIMES.len())]
+    RegisterOverflow { fraction: usize, register: usize },
+
+    #[error("Fraction {fraction} has zero on one side, which is not allowed")]
+    ZeroInFraction { fraction: usize },
+
+    #[error("Fraction {fraction} can't be stored in the prime basis: {source}")]
+    Basis {
+        fraction: usize,
+        #[source]
+        source: primebasis::Error,
+    },
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ExecError {
+    #[error("Overflow after {step} steps, multiplying by fraction {fraction}")]
+    Overflow { step: usize, fraction: usize },
+}
+
+/// Why `Program::convert` couldn't move a program to another number type.
+#[derive(Error, Debug, PartialEq)]
+pub enum ConvertError<E> {
+    #[error("Fraction {fraction} has a number too large for a u64")]
+    TooLarge { fraction: usize },
+
+    #[error("Fraction {fraction} can't be converted: {source}")]
+    Target {
+        fraction: usize,
+        #[source]
+        source: E,
+    },
+}
+
+/// What to do when a step of a `Program<u64>` overflows, for
+/// `Program::exec_with_policy`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    /// Keep the product modulo 2^64 and carry on, as release builds do for
+    /// plain `u64` multiplication.
+    Wrap,
+    /// Clamp the product at `u64::MAX` and carry on.
+    Saturate,
+    /// Stop the run with an `ExecError::Overflow`.
+    Error,
+}
 
 /// A program in Fractran: a list of fractions. Execution proceeds by
 /// multiplying the input number by each fraction in turn, overwriting the
@@ -15,170 +84,2588 @@ pub struct Program<T: FractranNat> {
 }
 
 impl<T: FractranNat> Program<T> {
-    /// Makes a new `Program` with the given nonempty list of fractions.
+    /// Attempts to make a new `Program` with the given list of fractions.
+    /// Returns `EmptyProgram` if the list is empty, as such a program can't be
+    /// run.
+    pub fn try_new(fracs: Vec<Fraction<T>>) -> Result<Program<T>, ProgramError> {
+        if fracs.is_empty() {
+            Err(ProgramError::EmptyProgram)
+        } else {
+            Ok(Program { fracs })
+        }
+    }
+
+    /// Makes a new `Program` with the given nonempty list of fractions,
+    /// panicking if the list is empty.
     pub fn new(fracs: Vec<Fraction<T>>) -> Program<T> {
-        Program{
-            fracs
+        match Program::try_new(fracs) {
+            Ok(prog) => prog,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Returns a `ProgramBuilder` with no fractions, to add them one at a time.
+    pub fn builder() -> ProgramBuilder<T> {
+        ProgramBuilder::new()
+    }
+
+    /// Returns the fractions that make up this program, in order.
+    pub fn fractions(&self) -> &[Fraction<T>] {
+        &self.fracs
+    }
+
+    /// Returns the number of fractions in this program.
+    pub fn len(&self) -> usize {
+        self.fracs.len()
+    }
+
+    /// Returns `true` if this program has no fractions.
+    pub fn is_empty(&self) -> bool {
+        self.fracs.is_empty()
+    }
+
+    /// Returns the program made of this program's fractions followed by
+    /// `other`'s. This is *not* running this program and then `other`: on
+    /// every step the first fraction that applies fires, so `other`'s fractions
+    /// can fire as soon as none of this program's apply, and this program's
+    /// fractions take over again whenever they apply to a later state. For
+    /// true sequencing over `PrimeBasis`, use `compose_via_registers`.
+    pub fn then(mut self, other: Program<T>) -> Program<T> {
+        self.fracs.extend(other.fracs);
+        self
+    }
+
+    /// Returns the same program, whose fractions are already in lowest terms:
+    /// every `Fraction` is reduced when it's made.
+    #[deprecated(note = "fractions are always stored in lowest terms")]
+    pub fn reduced(self) -> Program<T> {
+        self
+    }
+
+    /// Returns the program with `f` applied to each fraction, keeping their
+    /// order. This is how to write a transformation of a whole program, like
+    /// `shift_registers()`, one fraction at a time.
+    pub fn map_fractions<F: Fn(Fraction<T>) -> Fraction<T>>(self, f: F) -> Program<T> {
+        Program {
+            fracs: self.fracs.into_iter().map(f).collect(),
+        }
+    }
+
+    /// Converts the program to another number type, going through `u64`: each
+    /// numerator and denominator is turned into a `u64` with `to_u64_checked`
+    /// and then into a `U`. This moves a program between any of the backends,
+    /// like `u64` to `PrimeBasis` or `u128` to `BigUint`. Returns `TooLarge`
+    /// with the first fraction that doesn't fit in a `u64` along the way, and
+    /// `Target` with the first error from `U::try_from`.
+    pub fn convert<U>(self) -> Result<Program<U>, ConvertError<U::Error>>
+    where
+        U: FractranNat + TryFrom<u64>,
+    {
+        let fracs = self
+            .fracs
+            .into_iter()
+            .enumerate()
+            .map(|(fraction, frac)| {
+                let to_u = |n: &T| {
+                    let n = n
+                        .to_u64_checked()
+                        .ok_or(ConvertError::TooLarge { fraction })?;
+                    U::try_from(n).map_err(|source| ConvertError::Target { fraction, source })
+                };
+                Ok(Fraction::new(to_u(frac.num())?, to_u(frac.denom())?))
+            })
+            .collect::<Result<Vec<Fraction<U>>, _>>()?;
+        Ok(Program { fracs })
+    }
+
+    /// Returns an equivalent program with fewer fractions, behaving exactly
+    /// the same on every input under the first-applicable rule. A fraction is
+    /// removed if an earlier fraction's denominator divides its denominator,
+    /// because then the earlier one applies whenever it does and always fires
+    /// first. This removes repeated fractions and everything after a fraction
+    /// that always applies, like `x/1`.
+    ///
+    /// Fractions are stored in lowest terms, so a fraction applies to a state
+    /// exactly when its denominator divides the state, which is what makes
+    /// this safe.
+    pub fn optimize(self) -> Program<T> {
+        let mut fracs: Vec<Fraction<T>> = vec![];
+        for frac in self.fracs {
+            if !fracs.iter().any(|kept| kept.denom().divides(frac.denom())) {
+                fracs.push(frac);
+            }
+        }
+        Program { fracs }
+    }
+
+    /// Returns `true` if this program and `other` have the same fractions in
+    /// the same order, so `3/2` and `6/4` count as the same fraction. Every
+    /// `Fraction` is stored in lowest terms, so this is just `==`.
+    pub fn value_eq(&self, other: &Program<T>) -> bool
+    where
+        T: PartialEq,
+    {
+        self == other
+    }
+
+    /// Returns a key identifying this program up to how its fractions are
+    /// written, like `value_eq`: fractions are stored in lowest terms, and
+    /// `PrimeBasis` numbers never have trailing zero registers, so equal keys
+    /// hash the same. Collecting keys into a `HashSet` or `BTreeSet` drops
+    /// programs that only differ in representation, without keeping every
+    /// `Program` around.
+    pub fn canonical_key(&self) -> CanonicalKey<T> {
+        CanonicalKey(
+            self.fracs
+                .iter()
+                .map(|frac| (frac.num().clone(), frac.denom().clone()))
+                .collect(),
+        )
+    }
+
+    /// Returns the indices of the fractions that can never fire, because an
+    /// earlier fraction always applies (see `Fraction::always_applies`) and so
+    /// is always chosen first. For example, everything after `x/1` is dead.
+    pub fn dead_fractions(&self) -> Vec<usize> {
+        match self.fracs.iter().position(|frac| frac.always_applies()) {
+            Some(i) => (i + 1..self.fracs.len()).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Returns a copy of this program without any identity fractions like
+    /// `1/1`, which are sometimes added as a halt marker. Such fractions always
+    /// apply and never change the state, so reaching one makes the program
+    /// loop forever instead of halting: without them, it halts there instead.
+    /// Returns `EmptyProgram` if every fraction is an identity.
+    pub fn strip_halt(&self) -> Result<Program<T>, ProgramError>
+    where
+        T: PartialEq,
+    {
+        Program::try_new(
+            self.fracs
+                .iter()
+                .filter(|frac| !frac.is_identity())
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+/// Builds a `Program` one fraction at a time, checking the fractions only when
+/// `build()` is called.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ProgramBuilder<T: FractranNat> {
+    /// The numerator and denominator of each fraction added so far.
+    pairs: Vec<(T, T)>,
+}
+
+impl<T: FractranNat> Default for ProgramBuilder<T> {
+    fn default() -> Self {
+        ProgramBuilder { pairs: vec![] }
+    }
+}
+
+impl<T: FractranNat> ProgramBuilder<T> {
+    /// Makes a builder with no fractions yet.
+    pub fn new() -> ProgramBuilder<T> {
+        ProgramBuilder::default()
+    }
+
+    /// Adds the fraction `num/denom` after the ones already added.
+    pub fn frac(mut self, num: T, denom: T) -> ProgramBuilder<T> {
+        self.pairs.push((num, denom));
+        self
+    }
+
+    /// Makes the `Program`, returning `ZeroInFraction` with the index of the
+    /// first fraction that has zero on either side and `EmptyProgram` if no
+    /// fractions were added.
+    pub fn build(self) -> Result<Program<T>, ProgramError> {
+        let mut fracs = Vec::with_capacity(self.pairs.len());
+        for (i, (num, denom)) in self.pairs.into_iter().enumerate() {
+            let frac = Fraction::try_new(num, denom)
+                .map_err(|_| ProgramError::ZeroInFraction { fraction: i })?;
+            fracs.push(frac);
+        }
+        Program::try_new(fracs)
+    }
+}
+
+impl<T: FractranNat + fmt::Display> fmt::Display for Program<T> {
+    /// Writes the program in the conventional notation, e.g. `455/33, 11/13`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.fracs.iter().join(", "))
+    }
+}
+
+impl TryFrom<&[(u64, u64)]> for Program<u64> {
+    type Error = ProgramError;
+
+    /// Makes a program from `(numerator, denominator)` pairs, like
+    /// `ProgramBuilder`: returns `ZeroInFraction` with the index of the first
+    /// pair with a zero and `EmptyProgram` if there are no pairs.
+    fn try_from(pairs: &[(u64, u64)]) -> Result<Self, Self::Error> {
+        pairs
+            .iter()
+            .fold(ProgramBuilder::new(), |builder, &(num, denom)| {
+                builder.frac(num, denom)
+            })
+            .build()
+    }
+}
+
+impl<T: FractranNat> Add for Program<T> {
+    type Output = Program<T>;
+
+    /// Concatenates the fractions of the two programs, like `then`.
+    fn add(self, rhs: Program<T>) -> Program<T> {
+        self.then(rhs)
+    }
+}
+
+/// The outcome of running a program with some bound on its execution.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ExecOutcome<T: FractranNat> {
+    /// The program halted, with this final state.
+    Halted(T),
+    /// The step limit ran out before the program halted, with this last state.
+    LimitReached(T),
+    /// The stopping condition given to `exec_until` became true, with the
+    /// first state where it did.
+    PredicateMet(T),
+    /// The time limit given to `exec_with_timeout` ran out before the program
+    /// halted, with the last state.
+    TimedOut(T),
+}
+
+impl<T: FractranNat> ExecOutcome<T> {
+    /// Returns the last state of the run, regardless of how it ended.
+    pub fn into_state(self) -> T {
+        match self {
+            ExecOutcome::Halted(state)
+            | ExecOutcome::LimitReached(state)
+            | ExecOutcome::PredicateMet(state)
+            | ExecOutcome::TimedOut(state) => state,
         }
     }
 }
 
+/// A normalized form of a program, from `Program::canonical_key`, for
+/// deduplicating programs: two programs have equal keys exactly when they have
+/// the same fractions in the same order, which are always in lowest terms.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CanonicalKey<T: FractranNat>(Vec<(T, T)>);
+
+impl<T: FractranNat> CanonicalKey<T> {
+    /// Returns the `(numerator, denominator)` pair of each fraction, in order
+    /// and in lowest terms.
+    pub fn fractions(&self) -> &[(T, T)] {
+        &self.0
+    }
+}
+
+/// One item from `Program::lazy_exec_with_end`: either a step the program
+/// took, or the end of the run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Event<T: FractranNat> {
+    /// A fraction applied, giving this new state.
+    Step(T),
+    /// No fraction applied, so the program halted with this final state. This
+    /// is always the last event.
+    Halted(T),
+}
+
+impl<T: FractranNat> Event<T> {
+    /// Returns `true` if this is the `Halted` event that ends a run.
+    pub fn is_halted(&self) -> bool {
+        matches!(self, Event::Halted(_))
+    }
+
+    /// Returns the state this event carries, whichever kind it is.
+    pub fn into_state(self) -> T {
+        match self {
+            Event::Step(state) | Event::Halted(state) => state,
+        }
+    }
+}
+
+/// Which fraction each step of a program starts checking from.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ScanMode {
+    /// Start from the first fraction every time, as in standard Fractran.
+    #[default]
+    RestartFromTop,
+    /// Start from the fraction after the one that last fired, wrapping around
+    /// to the first fraction after the last. The program halts once every
+    /// fraction has been checked without one applying.
+    ContinueNext,
+}
+
 /// An iterator that holds the state of a program as it runs and, each time
-/// `next()` is called, continues to evaluate the program.
+/// `next()` is called, continues to evaluate the program. It can also be
+/// driven one step at a time with `step()`, which reports which fraction fired,
+/// for single-stepping a program in a debugger or visualizer. The fractions
+/// can be owned (`Vec`, the default) or borrowed from a `Program` (a slice), so
+/// the same program can be run on many inputs without cloning it.
+///
+/// It only holds the fractions and plain state, so it is `Send` and `Sync`
+/// whenever `T` is (and `P` is, for borrowed fractions). A run can be moved to
+/// another thread partway through and carried on there.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub(crate) struct Evaluator<T: FractranNat> {
+pub struct StepEvaluator<T: FractranNat, P: AsRef<[Fraction<T>]> = Vec<Fraction<T>>> {
     /// The program being run as a list of fractions.
-    program: Vec<Fraction<T>>,
+    program: P,
 
     /// The current state of the program.
     curr_state: T,
 
     /// Whether this program is over.
     finished: bool,
+
+    /// Where each step starts looking for a fraction to apply.
+    scan: ScanMode,
+
+    /// The fraction after the one that last fired, where `ContinueNext` starts.
+    next_index: usize,
 }
 
-impl<T: FractranNat> Iterator for Evaluator<T> {
+impl<T: FractranNat, P: AsRef<[Fraction<T>]>> Iterator for StepEvaluator<T, P> {
     type Item = T;
     fn next(&mut self) -> Option<Self::Item> {
+        self.step().map(|(_, state)| state)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
         if self.finished {
-            None
+            (0, Some(0))
         } else {
-            for frac in self.program.clone() {
-                if let StepResult::Changed(new_state) = frac.exec(self.curr_state.clone()) {
-                    self.curr_state = new_state;
-                    return Some(self.curr_state.clone());
-                }
-            }
-            // if here, then full evaluation without changing state
-            // program is finished
-            self.finished = true;
-            None
+            (0, None)
         }
     }
 }
 
-impl<T: FractranNat> Evaluator<T> {
-    /// Constructs an Evaluator from a Program and a starting state. Panics if
-    /// the given program is empty.
-    pub fn new(program: Vec<Fraction<T>>, input: T) -> impl Iterator<Item = T> {
-        if program.is_empty() {
-            panic!("Cannot run empty program!");
+// once `finished` is set, `step()` never runs the program again
+impl<T: FractranNat, P: AsRef<[Fraction<T>]>> FusedIterator for StepEvaluator<T, P> {}
+
+/// A `StepEvaluator` that borrows its program, as returned by
+/// `Program::evaluator` and `Program::resume`.
+pub type ResumableEvaluator<'a, T> = StepEvaluator<T, &'a [Fraction<T>]>;
+
+/// A snapshot of where a run of a program is, enough to continue it later with
+/// `Program::resume`. The fields are public so the snapshot can be stored
+/// however is convenient, for runs that take many sessions to finish.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Checkpoint<T: FractranNat> {
+    /// The current state of the program.
+    pub state: T,
+    /// Whether the program has halted.
+    pub finished: bool,
+}
+
+/// Measurements of a single run of a program, from `Program::exec_timed`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecStats<T: FractranNat> {
+    /// How the run ended, with its final state.
+    pub outcome: ExecOutcome<T>,
+    /// The number of steps that were run.
+    pub steps: usize,
+    /// How long the run took.
+    pub elapsed: Duration,
+    /// The average index of the fraction that fired on each step, or 0 if no
+    /// steps were run. Since every earlier fraction is checked first, this is
+    /// roughly how many fractions are tried per step.
+    pub mean_fraction_index: f64,
+}
+
+impl<T: FractranNat> ExecStats<T> {
+    /// Returns the number of steps run per second, or 0 if no time passed.
+    pub fn steps_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.steps as f64 / secs
+        } else {
+            0.0
         }
-        Evaluator {
+    }
+}
+
+/// How much register space a run of a `Program<PrimeBasis>` used, from
+/// `Program::register_profile`. The input counts as part of the run.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RegisterProfile {
+    /// The largest value each register reached, indexed by register, with no
+    /// trailing zeros.
+    pub max_exponents: Vec<u32>,
+    /// The most registers that were nonzero at the same time.
+    pub max_nonzero: usize,
+    /// The number of steps that were run.
+    pub steps: usize,
+}
+
+impl<T: FractranNat, P: AsRef<[Fraction<T>]>> StepEvaluator<T, P> {
+    /// Constructs a `StepEvaluator` from a program's fractions and a starting
+    /// state.
+    pub(crate) fn new(program: P, input: T) -> StepEvaluator<T, P> {
+        StepEvaluator {
             program,
             curr_state: input,
             finished: false,
+            scan: ScanMode::RestartFromTop,
+            next_index: 0,
+        }
+    }
+
+    /// Sets which fraction each step starts checking from. Checkpoints don't
+    /// record where a `ContinueNext` scan is, so set this before stepping.
+    pub fn with_scan_mode(mut self, scan: ScanMode) -> StepEvaluator<T, P> {
+        self.scan = scan;
+        self
+    }
+
+    /// Returns the current state, without running a step.
+    pub fn current(&self) -> &T {
+        &self.curr_state
+    }
+
+    /// Checks if the program has halted. This only becomes true once a step
+    /// has found no fraction to apply, so a state that can't step still reads
+    /// as unfinished until `step` or `next` is called on it.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns a `Checkpoint` of where this run is, to continue it later.
+    pub fn checkpoint(&self) -> Checkpoint<T> {
+        Checkpoint {
+            state: self.curr_state.clone(),
+            finished: self.finished,
+        }
+    }
+
+    /// Runs a single step of the program, returning the index of the fraction
+    /// that applied and the new state, or `None` if no fraction applies and
+    /// the program has halted. If `Itertools` is in scope, its deprecated
+    /// `step` method shadows this one, so call it as `StepEvaluator::step`.
+    pub fn step(&mut self) -> Option<(usize, T)> {
+        if self.finished {
+            return None;
+        }
+        let fracs = self.program.as_ref();
+        let start = match self.scan {
+            ScanMode::RestartFromTop => 0,
+            ScanMode::ContinueNext => self.next_index,
+        };
+        for i in (start..fracs.len()).chain(0..start) {
+            if let Some(new_state) = fracs[i].exec_ref(&self.curr_state) {
+                self.curr_state = new_state;
+                self.next_index = (i + 1) % fracs.len();
+                return Some((i, self.curr_state.clone()));
+            }
+        }
+        // if here, then full evaluation without changing state
+        // program is finished
+        self.finished = true;
+        None
+    }
+}
+
+/// Returns the outcome of a run that used up its step budget in `state`: still
+/// `Halted` if no fraction applies, as then the next step would have found
+/// that, and `LimitReached` otherwise. Fractions are stored in lowest terms,
+/// so one applies exactly when its denominator divides the state.
+fn outcome_at_limit<T: FractranNat>(fracs: &[Fraction<T>], state: T) -> ExecOutcome<T> {
+    if fracs.iter().any(|frac| frac.denom().divides(&state)) {
+        ExecOutcome::LimitReached(state)
+    } else {
+        ExecOutcome::Halted(state)
+    }
+}
+
+/// The outcome of running a program while watching for repeated states.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CycleOutcome<T: FractranNat> {
+    /// The program halted, with this final state.
+    Halted(T),
+    /// The program entered a loop. The input is counted as the state at index
+    /// 0, and the state at `start_index` is the first that repeats, coming back
+    /// every `period` steps. `states` lists the states in the loop in order,
+    /// starting with the repeated one.
+    Cycle {
+        start_index: usize,
+        period: usize,
+        states: Vec<T>,
+    },
+    /// The step limit ran out before the program halted or looped, with this
+    /// last state.
+    LimitReached(T),
+}
+
+/// Like `StepEvaluator`, but checks every multiplication for overflow, yielding a
+/// single `ExecError` and then stopping if one occurs.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct CheckedEvaluator<T: FractranNat> {
+    /// The program being run as a list of fractions.
+    program: Vec<Fraction<T>>,
+
+    /// The current state of the program.
+    curr_state: T,
+
+    /// The number of steps completed so far.
+    steps: usize,
+
+    /// Whether this program is over, either by halting or overflowing.
+    finished: bool,
+}
+
+impl<T: FractranNat> Iterator for CheckedEvaluator<T> {
+    type Item = Result<T, ExecError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        for (i, frac) in self.program.iter().enumerate() {
+            match frac.exec_checked_ref(&self.curr_state) {
+                Some(Some(new_state)) => {
+                    self.curr_state = new_state;
+                    self.steps += 1;
+                    return Some(Ok(self.curr_state.clone()));
+                }
+                None => {}
+                Some(None) => {
+                    self.finished = true;
+                    return Some(Err(ExecError::Overflow {
+                        step: self.steps,
+                        fraction: i,
+                    }));
+                }
+            }
+        }
+        self.finished = true;
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.finished {
+            (0, Some(0))
+        } else {
+            (0, None)
         }
     }
 }
 
+impl<T: FractranNat> FusedIterator for CheckedEvaluator<T> {}
+
 impl<T: FractranNat> Program<T> {
     /// Returns an iterator that lazily executes the program using a single
-    /// input, stopping if the program halts.
-    pub fn lazy_exec(self, input: T) -> impl Iterator<Item = T> {
-        Evaluator::new(self.fracs, input)
+    /// input, stopping if the program halts. The iterator is a
+    /// `StepEvaluator`, so it is `Send` and `Sync` whenever `T` is; the same
+    /// goes for the other `lazy_exec` variants, which only add closures that
+    /// capture states.
+    pub fn lazy_exec(self, input: T) -> impl FusedIterator<Item = T> {
+        StepEvaluator::new(self.fracs, input)
     }
 
-    /// Returns the final output of the program: this will obviously never
-    /// terminate if the program itself doesn't.
-    pub fn exec_to_completion(self, input: T) -> T {
+    /// Returns a `StepEvaluator` that runs the program from `input` one step
+    /// at a time, reporting which fraction fired on each step.
+    pub fn step_evaluator(self, input: T) -> StepEvaluator<T> {
+        StepEvaluator::new(self.fracs, input)
+    }
+
+    /// Runs the program from `input` and yields only the outputs that
+    /// `extract` finds in the states, skipping states where it returns `None`.
+    /// Programs often signal an output with a particular kind of state, so
+    /// this is how to read them: the README's prime generator outputs each
+    /// prime `p` as the state `2^p`, which `|s| s.is_power_of(2)` extracts.
+    /// The iterator ends when the program halts.
+    pub fn output_filter<F, O>(self, input: T, extract: F) -> impl Iterator<Item = O>
+    where
+        F: Fn(&T) -> Option<O>,
+    {
         self.lazy_exec(input)
-            .inspect(|step| {
-                dbg!(step);
-            })
-            .last()
-            .unwrap()
+            .filter_map(move |state| extract(&state))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::primebasis::PrimeBasis;
+    /// Like `lazy_exec`, but wraps each new state in `Event::Step` and, when
+    /// the program halts, yields a final `Event::Halted` with the last state
+    /// (which is the input if no fraction ever applies). A run cut short with
+    /// `take` doesn't end in `Halted`, so the two can be told apart.
+    pub fn lazy_exec_with_end(self, input: T) -> impl FusedIterator<Item = Event<T>> {
+        let mut evaluator = StepEvaluator::new(self.fracs, input);
+        let mut ended = false;
+        core::iter::from_fn(move || {
+            if ended {
+                return None;
+            }
+            match StepEvaluator::step(&mut evaluator) {
+                Some((_, state)) => Some(Event::Step(state)),
+                None => {
+                    ended = true;
+                    Some(Event::Halted(evaluator.curr_state.clone()))
+                }
+            }
+        })
+        .fuse()
+    }
 
-    /// Given two vectors of numerators and denominators initializes the program.
-    fn make_program(nums: Vec<u64>, denoms: Vec<u64>) -> Program<PrimeBasis> {
-        let mut prog: Vec<Fraction<PrimeBasis>> = vec![];
-        for (num, denom) in nums.into_iter().zip(denoms) {
-            prog.push(Fraction::new(
-                PrimeBasis::try_new(num).unwrap(),
-                PrimeBasis::try_new(denom).unwrap(),
-            ));
+    /// Like `lazy_exec`, but pairs each new state with the index of the fraction
+    /// that produced it, which is enough to build execution traces or find
+    /// fractions that never fire.
+    pub fn lazy_exec_with_rule(self, input: T) -> impl Iterator<Item = (usize, T)> {
+        let mut evaluator = StepEvaluator::new(self.fracs, input);
+        core::iter::from_fn(move || StepEvaluator::step(&mut evaluator))
+    }
+
+    /// Like `lazy_exec`, but yields the input itself before any of the states
+    /// the program produces, so the whole path the program takes is visible.
+    /// The iterator still ends when the program halts.
+    pub fn trajectory(self, input: T) -> impl Iterator<Item = T> {
+        core::iter::once(input.clone()).chain(self.lazy_exec(input))
+    }
+
+    /// Like `trajectory`, but pairs each state with its step number, so the
+    /// input is step 0.
+    pub fn lazy_exec_indexed(self, input: T) -> impl Iterator<Item = (usize, T)> {
+        self.trajectory(input).enumerate()
+    }
+
+    /// Like `lazy_exec`, but pairs each state with whether its value differs
+    /// from the state before it. Every step applies some fraction, but an
+    /// identity fraction like `1/1` leaves the value the same, so `false`
+    /// marks a step that made no progress: a program stuck on one is at a
+    /// fixpoint and will never halt.
+    pub fn lazy_exec_annotated(self, input: T) -> impl Iterator<Item = (T, bool)>
+    where
+        T: PartialEq,
+    {
+        let mut prev = input.clone();
+        self.lazy_exec(input).map(move |state| {
+            let changed = state != prev;
+            prev = state.clone();
+            (state, changed)
+        })
+    }
+
+    /// Like `lazy_exec`, but checks each multiplication for overflow. If one
+    /// would overflow, the iterator yields an `ExecError` and then stops,
+    /// instead of silently continuing with a garbage state.
+    pub fn lazy_exec_checked(self, input: T) -> impl FusedIterator<Item = Result<T, ExecError>> {
+        CheckedEvaluator {
+            program: self.fracs,
+            curr_state: input,
+            steps: 0,
+            finished: false,
         }
-        Program { fracs: prog }
     }
 
-    #[test]
-    fn test_basic_program() {
-        let div_then_stop = Program {
-            fracs: vec![Fraction::new(1_u64, 2_u64)],
-        };
-        let mut iter = div_then_stop.clone().lazy_exec(4_u64);
-        assert_eq!(iter.next(), Some(2_u64));
-        assert_eq!(iter.next(), Some(1_u64));
-        assert_eq!(iter.next(), None);
-        assert_eq!(div_then_stop.exec_to_completion(4_u64), 1_u64);
+    /// Returns the final output of the program, or the input if no fraction
+    /// applies to it: this will obviously never terminate if the program itself
+    /// doesn't.
+    pub fn exec_to_completion(self, input: T) -> T {
+        self.exec_to_completion_ref(input)
     }
 
-    #[test]
-    fn test_multiply() {
-        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+    /// Returns a `ResumableEvaluator` that runs this program from `input`,
+    /// borrowing the program. Its progress can be saved at any point with
+    /// `checkpoint()` and continued with `resume`.
+    pub fn evaluator(&self, input: T) -> ResumableEvaluator<'_, T> {
+        StepEvaluator::new(&self.fracs[..], input)
+    }
 
-        // here, we input 2^3 * 3^2, so we should get 5^6
-        assert_eq!(
-            mult_pb
-                .exec_to_completion(PrimeBasis::try_new(72).unwrap())
-                .value(),
-            5_u64.pow(6)
-        );
+    /// Returns a `ResumableEvaluator` that continues a run of this program from
+    /// a `Checkpoint`, exactly as if it had never stopped.
+    pub fn resume(&self, checkpoint: Checkpoint<T>) -> ResumableEvaluator<'_, T> {
+        StepEvaluator {
+            program: &self.fracs[..],
+            curr_state: checkpoint.state,
+            finished: checkpoint.finished,
+            scan: ScanMode::RestartFromTop,
+            next_index: 0,
+        }
+    }
+
+    /// Like `lazy_exec`, but borrows the program instead of consuming it, so it
+    /// can be run on many inputs without being cloned.
+    pub fn lazy_exec_ref(&self, input: T) -> impl FusedIterator<Item = T> + '_ {
+        StepEvaluator::new(&self.fracs[..], input)
+    }
+
+    /// Like `exec_to_completion`, but borrows the program instead of consuming
+    /// it, so it can be run on many inputs without being cloned.
+    pub fn exec_to_completion_ref(&self, input: T) -> T {
+        let mut evaluator = StepEvaluator::new(&self.fracs[..], input);
+        while evaluator.next().is_some() {}
+        evaluator.curr_state
+    }
+
+    /// Like `exec_to_completion`, but calls `observer` on each new state as the
+    /// program produces it, not including the input. This is useful for
+    /// logging, collecting statistics, or reporting progress.
+    pub fn exec_with_observer<F: FnMut(&T)>(self, input: T, mut observer: F) -> T {
+        let mut evaluator = StepEvaluator::new(self.fracs, input);
+        for state in evaluator.by_ref() {
+            observer(&state);
+        }
+        evaluator.curr_state
+    }
+
+    /// Runs the program for at most `max_steps` steps, returning `Halted` with
+    /// the final state if the program halts within that budget and
+    /// `LimitReached` with the last state seen otherwise. Checking that no
+    /// fraction applies isn't counted as a step, so a program that halts on
+    /// exactly its last allowed step is `Halted`, agreeing with `halts_within`.
+    pub fn exec_with_limit(self, input: T, max_steps: usize) -> ExecOutcome<T> {
+        self.exec_with_limit_ref(input, max_steps)
     }
-    #[test]
-    fn test_readme_primes() {
-        let nums: Vec<u64> = vec![17, 78, 19, 23, 29,
-                                  77, 95, 77, 1, 11,
-                                  13, 15, 15, 55];
-        let denoms: Vec<u64> = vec![91, 85, 51, 38, 33,
-                                    29, 23, 19, 17, 13,
-                                    11, 14, 2, 1];
-        let fracs: Vec<Fraction<u64>> = nums.into_iter()
-                                            .zip(denoms)
-                                            .map(|(num, denom)| Fraction::new(num, denom))
-                                            .collect();
 
-        let prog = Program::new(fracs);
-        let mut primes = vec![];
-        for out in prog.lazy_exec(2).take(2000) {
-            if out.is_power_of_two() {
-                primes.push(out.trailing_zeros());
+    /// Like `exec_with_limit`, but borrows the program instead of consuming it.
+    pub fn exec_with_limit_ref(&self, input: T, max_steps: usize) -> ExecOutcome<T> {
+        let mut evaluator = StepEvaluator::new(&self.fracs[..], input);
+        for _ in 0..max_steps {
+            if evaluator.next().is_none() {
+                return ExecOutcome::Halted(evaluator.curr_state);
             }
         }
-        assert_eq!(primes, vec![2, 3, 5, 7]);
+        outcome_at_limit(&self.fracs, evaluator.curr_state)
     }
 
-    #[test]
-    fn test_readme_primes_2e() {
-        let nums: Vec<u64> = vec![17, 78, 19, 23, 29,
-                                  77, 95, 77, 1, 11,
-                                  13, 15, 15, 55];
-        let denoms: Vec<u64> = vec![91, 85, 51, 38, 33,
-                                    29, 23, 19, 17, 13,
-                                    11, 14, 2, 1];
-        let fracs: Vec<Fraction<PrimeBasis>> = nums.into_iter()
-                                                   .zip(denoms)
-                                                   .map(|(num, denom)| Fraction::new(
-                                                       PrimeBasis::try_new(num).unwrap(),
-                                                       PrimeBasis::try_new(denom).unwrap(),
-                                                   ))
-                                                   .collect();
-
-        let prog = Program::new(fracs);
-        let mut primes = vec![];
-        for out_pb in prog.lazy_exec(PrimeBasis::try_new(2).unwrap())
-                          .take(100_000) {
-            if out_pb.exps[1..].iter().all(|&exp| exp == 0) {
-                primes.push(out_pb.exps[0]);
-            }
-        }
-        assert_eq!(primes, vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37]);
+    /// Like `exec_with_limit`, but with `scan` choosing which fraction each
+    /// step starts checking from. `ScanMode::RestartFromTop` is the same as
+    /// `exec_with_limit`.
+    pub fn exec_with_scan(self, input: T, scan: ScanMode, max_steps: usize) -> ExecOutcome<T> {
+        let mut evaluator = StepEvaluator::new(self.fracs, input).with_scan_mode(scan);
+        for _ in 0..max_steps {
+            if StepEvaluator::step(&mut evaluator).is_none() {
+                return ExecOutcome::Halted(evaluator.curr_state);
+            }
+        }
+        outcome_at_limit(&evaluator.program, evaluator.curr_state)
+    }
+
+    /// Runs the program until either it halts, returning `Halted`, or
+    /// `predicate` is true of the current state, returning `PredicateMet`. The
+    /// input is checked too, so if `predicate` is already true of it the
+    /// program doesn't run at all. This is useful for stopping runaway programs
+    /// once their state grows too large. This will never terminate if the
+    /// program doesn't and the predicate never becomes true.
+    pub fn exec_until<F: Fn(&T) -> bool>(self, input: T, predicate: F) -> ExecOutcome<T> {
+        if predicate(&input) {
+            return ExecOutcome::PredicateMet(input);
+        }
+        let mut evaluator = StepEvaluator::new(self.fracs, input);
+        for state in evaluator.by_ref() {
+            if predicate(&state) {
+                return ExecOutcome::PredicateMet(state);
+            }
+        }
+        ExecOutcome::Halted(evaluator.curr_state)
+    }
+
+    /// Runs a variant of Fractran where each step applies every fraction that
+    /// can be applied, instead of only the first. In each step, the fractions
+    /// are tried in order and the state is updated after each one that applies,
+    /// so later fractions see the result of earlier ones; in canonical Fractran,
+    /// the step would instead end after the first fraction applies and start
+    /// over from the beginning of the list. The program halts after a step in
+    /// which no fraction applies. Like `exec_with_limit`, this runs for at most
+    /// `max_steps` steps and returns `LimitReached` if it runs out.
+    pub fn exec_greedy_all(self, input: T, max_steps: usize) -> ExecOutcome<T> {
+        let mut state = input;
+        for _ in 0..max_steps {
+            let mut any_changed = false;
+            for frac in &self.fracs {
+                state = match frac.exec(state) {
+                    StepResult::Changed(new_state) => {
+                        any_changed = true;
+                        new_state
+                    }
+                    StepResult::Unchanged(state) => state,
+                };
+            }
+            if !any_changed {
+                return ExecOutcome::Halted(state);
+            }
+        }
+        outcome_at_limit(&self.fracs, state)
+    }
+
+    /// Runs the program for at most `max_steps` steps and returns the largest
+    /// state seen, counting the input, without storing every state. For
+    /// `PrimeBasis`, the comparison never overflows, so this works even when
+    /// the states are far too large for `value()`.
+    pub fn max_state(self, input: T, max_steps: usize) -> T
+    where
+        T: Ord,
+    {
+        self.trajectory(input)
+            .take(max_steps.saturating_add(1))
+            .max()
+            .expect("trajectory always includes the input")
+    }
+
+    /// Runs the program like `exec_with_limit`, also measuring how long the run
+    /// took, how many steps it ran, and which fractions fired, for
+    /// benchmarking. Needs the `std` feature, for its clock.
+    #[cfg(feature = "std")]
+    pub fn exec_timed(self, input: T, max_steps: usize) -> ExecStats<T> {
+        let start = std::time::Instant::now();
+        let mut evaluator = StepEvaluator::new(self.fracs, input);
+        let (mut steps, mut index_total) = (0, 0);
+        let halted = loop {
+            if steps == max_steps {
+                break false;
+            }
+            match StepEvaluator::step(&mut evaluator) {
+                Some((i, _)) => {
+                    steps += 1;
+                    index_total += i;
+                }
+                None => break true,
+            }
+        };
+        let elapsed = start.elapsed();
+        let state = evaluator.curr_state;
+        ExecStats {
+            outcome: if halted {
+                ExecOutcome::Halted(state)
+            } else {
+                outcome_at_limit(&evaluator.program, state)
+            },
+            steps,
+            elapsed,
+            mean_fraction_index: if steps == 0 {
+                0.0
+            } else {
+                index_total as f64 / steps as f64
+            },
+        }
+    }
+
+    /// Runs the program until it halts, returning `Halted`, or until `timeout`
+    /// has passed, returning `TimedOut`. This is more useful than a step limit
+    /// when steps get slower as the state grows. The clock is only checked
+    /// every 1024 steps, so the run can go a little over. Needs the `std`
+    /// feature, for its clock.
+    #[cfg(feature = "std")]
+    pub fn exec_with_timeout(self, input: T, timeout: Duration) -> ExecOutcome<T> {
+        const CHECK_EVERY: usize = 1024;
+        let start = std::time::Instant::now();
+        let mut evaluator = StepEvaluator::new(self.fracs, input);
+        loop {
+            for _ in 0..CHECK_EVERY {
+                if StepEvaluator::step(&mut evaluator).is_none() {
+                    return ExecOutcome::Halted(evaluator.curr_state);
+                }
+            }
+            if start.elapsed() >= timeout {
+                return ExecOutcome::TimedOut(evaluator.curr_state);
+            }
+        }
+    }
+
+    /// Runs the program on each input in turn, as with `exec_with_limit`, and
+    /// returns the outcomes in the same order as the inputs. The program is
+    /// borrowed for every run, so its fractions are never cloned.
+    pub fn exec_many(
+        &self,
+        inputs: impl IntoIterator<Item = T>,
+        max_steps: usize,
+    ) -> Vec<ExecOutcome<T>> {
+        inputs
+            .into_iter()
+            .map(|input| self.exec_with_limit_ref(input, max_steps))
+            .collect()
+    }
+
+    /// Returns `true` if the program halts after taking at most `max_steps`
+    /// steps from `input`, and `false` if it's still running after that many.
+    /// A program that halts on exactly its last allowed step counts as
+    /// halting, so `halts_within(x, n)` is the same as `exec_with_limit(x, n)`
+    /// returning `Halted`.
+    pub fn halts_within(self, input: T, max_steps: usize) -> bool {
+        self.lazy_exec(input).nth(max_steps).is_none()
+    }
+
+    /// Runs the program for at most `max_steps` steps and counts how many
+    /// times each fraction fired, aligned with the order of the fractions.
+    /// Fractions with a count of zero never fired, and the largest counts show
+    /// where the program spends its time.
+    pub fn coverage(self, input: T, max_steps: usize) -> Vec<usize> {
+        let mut counts = vec![0; self.len()];
+        for (i, _) in self.lazy_exec_with_rule(input).take(max_steps) {
+            counts[i] += 1;
+        }
+        counts
+    }
+
+    /// Returns the distinct states the program passes through from `input`,
+    /// in order and starting with the input itself. This stops when the program
+    /// halts, when a state repeats (so the rest would loop), or once
+    /// `max_states` states have been collected, whichever comes first. Needs
+    /// the `std` feature, for its `HashSet`.
+    #[cfg(feature = "std")]
+    pub fn reachable_states(self, input: T, max_states: usize) -> Vec<T>
+    where
+        T: Hash + Eq,
+    {
+        let mut seen = HashSet::new();
+        let mut states = vec![];
+        for state in self.trajectory(input).take(max_states) {
+            if !seen.insert(state.clone()) {
+                break;
+            }
+            states.push(state);
+        }
+        states
+    }
+
+    /// Runs the program for at most `max_steps` steps, remembering every state
+    /// it passes through so that it can stop as soon as one repeats, which
+    /// means the program will loop forever. Note that this stores every state
+    /// seen, so the memory used grows with `max_steps`. Needs the `std`
+    /// feature, for its `HashMap`.
+    #[cfg(feature = "std")]
+    pub fn exec_detect_cycle(self, input: T, max_steps: usize) -> CycleOutcome<T>
+    where
+        T: Hash + Eq,
+    {
+        let mut seen = HashMap::new();
+        seen.insert(input.clone(), 0);
+        let mut evaluator = StepEvaluator::new(self.fracs, input);
+        for index in 1..=max_steps {
+            let state = match evaluator.next() {
+                Some(state) => state,
+                None => return CycleOutcome::Halted(evaluator.curr_state),
+            };
+            if let Some(&start_index) = seen.get(&state) {
+                let period = index - start_index;
+                // the evaluator is back at the start of the loop, so the rest of
+                // the loop is just the next few states
+                let states = core::iter::once(state)
+                    .chain(evaluator.take(period - 1))
+                    .collect();
+                return CycleOutcome::Cycle {
+                    start_index,
+                    period,
+                    states,
+                };
+            }
+            seen.insert(state, index);
+        }
+        match outcome_at_limit(&evaluator.program, evaluator.curr_state) {
+            ExecOutcome::Halted(state) => CycleOutcome::Halted(state),
+            outcome => CycleOutcome::LimitReached(outcome.into_state()),
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: FractranNat + Send + Sync> Program<T> {
+    /// Like `exec_many`, but runs the inputs in parallel on the `rayon` thread
+    /// pool. The outcomes are still in the same order as the inputs.
+    pub fn par_exec_many(&self, inputs: &[T], max_steps: usize) -> Vec<ExecOutcome<T>> {
+        use rayon::prelude::*;
+
+        inputs
+            .par_iter()
+            .map(|input| self.exec_with_limit_ref(input.clone(), max_steps))
+            .collect()
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<T: FractranNat> Program<T> {
+    /// Runs a stochastic variant of Fractran where, on each step, the fraction
+    /// to apply is chosen uniformly at random from all of the fractions that
+    /// can be applied, instead of always taking the first. A fraction can be
+    /// applied when its denominator divides the state, the same rule
+    /// `StepEvaluator` uses. Passing a seeded `rng` makes runs reproducible.
+    /// Like `exec_with_limit`, this runs for at
+    /// most `max_steps` steps and returns `LimitReached` if it runs out.
+    pub fn exec_random<R: rand::Rng>(
+        self,
+        input: T,
+        rng: &mut R,
+        max_steps: usize,
+    ) -> ExecOutcome<T> {
+        let mut state = input;
+        for _ in 0..max_steps {
+            let applicable: Vec<&Fraction<T>> = self
+                .fracs
+                .iter()
+                .filter(|frac| frac.denom().divides(&state))
+                .collect();
+            if applicable.is_empty() {
+                return ExecOutcome::Halted(state);
+            }
+            let frac = applicable[rng.gen_range(0..applicable.len())];
+            state = frac.exec(state).into_state();
+        }
+        outcome_at_limit(&self.fracs, state)
+    }
+}
+
+impl Program<PrimeBasis> {
+    /// Makes a program from `(numerator, denominator)` pairs, converting each
+    /// side to a `PrimeBasis`. Returns `Basis` with the index of the first
+    /// fraction that can't be converted, `ZeroInFraction` if any side is zero,
+    /// and `EmptyProgram` if there are no pairs.
+    pub fn from_u64_fractions(pairs: &[(u64, u64)]) -> Result<Program<PrimeBasis>, ProgramError> {
+        let mut builder = ProgramBuilder::new();
+        for (i, &(num, denom)) in pairs.iter().enumerate() {
+            if num == 0 || denom == 0 {
+                return Err(ProgramError::ZeroInFraction { fraction: i });
+            }
+            let to_pb = |n| {
+                PrimeBasis::try_new(n).map_err(|source| ProgramError::Basis {
+                    fraction: i,
+                    source,
+                })
+            };
+            builder = builder.frac(to_pb(num)?, to_pb(denom)?);
+        }
+        builder.build()
+    }
+
+    /// Returns the indices of every register (prime exponent) that the program
+    /// reads or writes: that is, every register that's nonzero in any
+    /// numerator or denominator.
+    pub fn registers_used(&self) -> BTreeSet<usize> {
+        self.fracs
+            .iter()
+            .flat_map(|frac| vec![frac.num(), frac.denom()])
+            .flat_map(|pb| {
+                pb.exps
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &exp)| exp != 0)
+                    .map(|(i, _)| i)
+            })
+            .collect()
+    }
+
+    /// Like `exec_with_limit`, but clamps every register to at most `cap` after
+    /// each step with `PrimeBasis::with_register_cap`, modeling a finite-state
+    /// machine whose registers can only count so high. The input is clamped
+    /// too. Since there are only finitely many capped states, a capped program
+    /// that doesn't halt must eventually loop.
+    pub fn exec_with_register_cap(
+        self,
+        input: PrimeBasis,
+        cap: u32,
+        max_steps: usize,
+    ) -> ExecOutcome<PrimeBasis> {
+        let mut state = input.with_register_cap(cap);
+        for _ in 0..max_steps {
+            match self.fracs.iter().find_map(|frac| frac.exec_ref(&state)) {
+                Some(new_state) => state = new_state.with_register_cap(cap),
+                None => return ExecOutcome::Halted(state),
+            }
+        }
+        outcome_at_limit(&self.fracs, state)
+    }
+
+    /// Moves every register the program uses up by `offset`, in every numerator
+    /// and denominator, with `PrimeBasis::shift_registers`. The shifted program
+    /// does the same thing on shifted inputs, and leaves the first `offset`
+    /// registers alone, so it can be combined with a program that uses them.
+    pub fn shift_registers(self, offset: usize) -> Program<PrimeBasis> {
+        self.map_fractions(|frac| {
+            Fraction::new(
+                frac.num().clone().shift_registers(offset),
+                frac.denom().clone().shift_registers(offset),
+            )
+        })
+    }
+
+    /// Returns a program that runs this program until it halts and then runs
+    /// `other` on the result, along with the index of the guard register the
+    /// input must have set to 1 to start it. Four registers past any either
+    /// program uses are taken as guards: one marks each phase, and each
+    /// fraction moves its phase's guard to a partner register that the first
+    /// fraction of the phase immediately moves back, so a phase's fractions
+    /// can only fire while its guard is set. Once neither phase applies, the
+    /// guard is removed and the final state is `other`'s output. Each step of
+    /// either program takes two steps of the result.
+    pub fn compose_via_registers(self, other: Program<PrimeBasis>) -> (Program<PrimeBasis>, usize) {
+        let first_free = self
+            .registers_used()
+            .into_iter()
+            .chain(other.registers_used())
+            .max()
+            .map_or(0, |reg| reg + 1);
+        let guard = |offset: usize| {
+            let mut pb = PrimeBasis::from(vec![]);
+            pb.set_register(first_free + offset, 1);
+            pb
+        };
+        let one = PrimeBasis::from(vec![]);
+        let mut fracs = vec![];
+        for (phase, prog) in vec![self, other].into_iter().enumerate() {
+            let (on, moved) = (guard(2 * phase), guard(2 * phase + 1));
+            fracs.push(Fraction::new(on.clone(), moved.clone()));
+            for frac in prog.fracs {
+                let (num, denom) = (frac.num().clone(), frac.denom().clone());
+                fracs.push(Fraction::new(num * moved.clone(), denom * on.clone()));
+            }
+            // hand over to the next phase, or finish
+            let next = if phase == 0 { guard(2) } else { one.clone() };
+            fracs.push(Fraction::new(next, on));
+        }
+        (Program { fracs }, first_free)
+    }
+
+    /// Returns the smallest state on which the fraction at `frac_index` is the
+    /// first to apply, or `None` if there is no such state or no such
+    /// fraction. A fraction applies exactly when its denominator divides the
+    /// state, so every state that triggers it is a multiple of its
+    /// denominator, and a multiple only adds to each register. Adding to
+    /// registers can't stop an earlier denominator from dividing the state, so
+    /// the denominator itself is the answer unless an earlier denominator
+    /// divides it, in which case the fraction can never fire first.
+    pub fn min_trigger_state(&self, frac_index: usize) -> Option<PrimeBasis> {
+        let denom = self.fracs.get(frac_index)?.denom();
+        if self.fracs[..frac_index]
+            .iter()
+            .any(|earlier| earlier.denom().divides(denom))
+        {
+            None
+        } else {
+            Some(denom.clone())
+        }
+    }
+
+    /// Runs the program from `input` for at most `max_steps` steps, tracking
+    /// the largest value of each register and the most registers in use at
+    /// once: the program's memory footprint as a register machine. This is
+    /// tracked as the program runs, so no states are stored.
+    pub fn register_profile(self, input: PrimeBasis, max_steps: usize) -> RegisterProfile {
+        let mut profile = RegisterProfile::default();
+        for state in self.trajectory(input).take(max_steps.saturating_add(1)) {
+            if profile.max_exponents.len() < state.exps.len() {
+                profile.max_exponents.resize(state.exps.len(), 0);
+            }
+            for (max, &exp) in profile.max_exponents.iter_mut().zip(&state.exps) {
+                *max = (*max).max(exp);
+            }
+            profile.max_nonzero = profile.max_nonzero.max(state.num_nonzero_registers());
+            profile.steps += 1;
+        }
+        // the input isn't a step
+        profile.steps -= 1;
+        profile
+    }
+
+    /// Runs the program from `input` for at most `max_steps` steps and returns
+    /// the path it takes as a Graphviz DOT digraph. Each distinct state is a
+    /// node labeled with its nonzero registers, like `r0=3 r2=2` (or `1` if
+    /// there are none), and each step is an edge labeled with the index of the
+    /// fraction that fired. A fraction that doesn't change the state gives a
+    /// self-loop, and a program that loops gives a back edge, after which the
+    /// trace stops because it would only repeat itself. Needs the `std`
+    /// feature, for its `HashMap`.
+    #[cfg(feature = "std")]
+    pub fn trace_to_dot(self, input: PrimeBasis, max_steps: usize) -> String {
+        let mut ids: HashMap<PrimeBasis, usize> = HashMap::new();
+        let mut nodes = vec![];
+        let mut edges = vec![];
+        // returns the state's id, and whether it was seen before
+        let mut node_id = |state: PrimeBasis, nodes: &mut Vec<String>| {
+            if let Some(&id) = ids.get(&state) {
+                return (id, true);
+            }
+            let id = ids.len();
+            let label = match state.display_registers().to_string() {
+                regs if regs.is_empty() => "1".to_string(),
+                regs => regs,
+            };
+            nodes.push(format!("    s{} [label=\"{}\"];", id, label));
+            ids.insert(state, id);
+            (id, false)
+        };
+
+        let (mut curr, _) = node_id(input.clone(), &mut nodes);
+        for (rule, state) in self.lazy_exec_with_rule(input).take(max_steps) {
+            let (next, seen) = node_id(state, &mut nodes);
+            edges.push(format!("    s{} -> s{} [label=\"{}\"];", curr, next, rule));
+            if seen {
+                // the program is deterministic, so it's looping from here on
+                break;
+            }
+            curr = next;
+        }
+
+        let lines = nodes.into_iter().chain(edges).join("\n");
+        format!("digraph trace {{\n{}\n}}\n", lines)
+    }
+
+    /// Checks that every numerator and denominator only uses registers that
+    /// have a corresponding prime in `PRIMES`, so that the program can be run
+    /// and displayed without issue. Returns `RegisterOverflow` with the first
+    /// offending fraction and register otherwise.
+    pub fn validate(&self) -> Result<(), ProgramError> {
+        for (i, frac) in self.fracs.iter().enumerate() {
+            for pb in &[frac.num(), frac.denom()] {
+                let highest = pb.exps.iter().rposition(|&exp| exp != 0);
+                if let Some(register) = highest.filter(|&reg| reg >= PRIMES.len()) {
+                    return Err(ProgramError::RegisterOverflow {
+                        fraction: i,
+                        register,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Program<u64> {
+    /// Lazily lists every program of exactly `length` fractions whose
+    /// numerators are at most `max_num` and whose denominators are at most
+    /// `max_denom`, for brute-force searches. Only fractions in lowest terms
+    /// are used, since other fractions are stored the same way, so no program
+    /// is listed twice. There are `F^length` programs, where `F` is the number
+    /// of such fractions, so only the first one is ever built up front.
+    pub fn enumerate_candidates(
+        max_num: u64,
+        max_denom: u64,
+        length: usize,
+    ) -> impl Iterator<Item = Program<u64>> {
+        let fracs: Vec<Fraction<u64>> = (1..=max_num)
+            .cartesian_product(1..=max_denom)
+            .filter(|(num, denom)| FractranNat::gcd(num, denom) == 1)
+            .map(|(num, denom)| Fraction::new(num, denom))
+            .collect();
+        (0..length)
+            .map(move |_| fracs.clone().into_iter())
+            .multi_cartesian_product()
+            .filter_map(|fracs| Program::try_new(fracs).ok())
+    }
+
+    /// Converts every numerator and denominator to a `PrimeBasis`, giving the
+    /// same program without the risk of overflow. Returns `Basis` with the
+    /// index of the first fraction that has a prime factor too large to store.
+    pub fn into_prime_basis(self) -> Result<Program<PrimeBasis>, ProgramError> {
+        let pairs: Vec<(u64, u64)> = self
+            .fracs
+            .iter()
+            .map(|frac| (*frac.num(), *frac.denom()))
+            .collect();
+        Program::from_u64_fractions(&pairs)
+    }
+
+    /// Runs the program for at most `max_steps` steps like `exec_with_limit`,
+    /// but handles a step that overflows a `u64` according to `policy`. Only
+    /// `OverflowPolicy::Error` ever returns `Err`, which says which step and
+    /// fraction overflowed. Wrapped or saturated states are no longer the
+    /// product the program asked for, so the rest of the run is only
+    /// meaningful if the program was expected to stay in range.
+    pub fn exec_with_policy(
+        self,
+        input: u64,
+        policy: OverflowPolicy,
+        max_steps: usize,
+    ) -> Result<ExecOutcome<u64>, ExecError> {
+        let mut state = input;
+        for step in 0..max_steps {
+            let (i, frac) = match self
+                .fracs
+                .iter()
+                .enumerate()
+                .find(|(_, frac)| state % frac.denom() == 0)
+            {
+                Some(applied) => applied,
+                None => return Ok(ExecOutcome::Halted(state)),
+            };
+            // the fraction is in lowest terms, so dividing first is exact
+            let quotient = state / frac.denom();
+            state = match policy {
+                OverflowPolicy::Wrap => quotient.wrapping_mul(*frac.num()),
+                OverflowPolicy::Saturate => quotient.saturating_mul(*frac.num()),
+                OverflowPolicy::Error => quotient
+                    .checked_mul(*frac.num())
+                    .ok_or(ExecError::Overflow { step, fraction: i })?,
+            };
+        }
+        Ok(outcome_at_limit(&self.fracs, state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixedbasis::FixedBasis;
+
+    /// Given two vectors of numerators and denominators initializes the program.
+    fn make_program(nums: Vec<u64>, denoms: Vec<u64>) -> Program<PrimeBasis> {
+        let pairs: Vec<(u64, u64)> = nums.into_iter().zip(denoms).collect();
+        Program::from_u64_fractions(&pairs).unwrap()
+    }
+
+    #[test]
+    fn test_basic_program() {
+        let div_then_stop = Program {
+            fracs: vec![Fraction::new(1_u64, 2_u64)],
+        };
+        let mut iter = div_then_stop.clone().lazy_exec(4_u64);
+        assert_eq!(iter.next(), Some(2_u64));
+        assert_eq!(iter.next(), Some(1_u64));
+        assert_eq!(iter.next(), None);
+        assert_eq!(div_then_stop.clone().exec_to_completion(4_u64), 1_u64);
+        assert_eq!(div_then_stop.exec_to_completion(3_u64), 3_u64);
+    }
+
+    #[test]
+    fn test_accessors() {
+        let prog = Program::new(vec![
+            Fraction::new(3_u64, 2_u64),
+            Fraction::new(1_u64, 3_u64),
+        ]);
+        assert_eq!(prog.len(), 2);
+        assert!(!prog.is_empty());
+        assert_eq!(prog.fractions()[1], Fraction::new(1_u64, 3_u64));
+    }
+
+    #[test]
+    fn test_empty_program() {
+        assert_eq!(
+            Program::<u64>::try_new(vec![]),
+            Err(ProgramError::EmptyProgram)
+        );
+        assert!(Program::try_new(vec![Fraction::new(1_u64, 2_u64)]).is_ok());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_new_panics_on_empty() {
+        Program::<u64>::new(vec![]);
+    }
+
+    #[test]
+    fn test_display() {
+        let prog = Program::new(vec![
+            Fraction::new(455_u64, 33_u64),
+            Fraction::new(11_u64, 13_u64),
+            Fraction::new(1_u64, 11_u64),
+        ]);
+        assert_eq!(prog.to_string(), "455/33, 11/13, 1/11");
+    }
+
+    #[test]
+    fn test_exec_with_observer() {
+        let div_then_stop = Program::new(vec![Fraction::new(1_u64, 2_u64)]);
+        let mut seen = vec![];
+        let last = div_then_stop.exec_with_observer(8_u64, |&state| seen.push(state));
+        assert_eq!(last, 1);
+        assert_eq!(seen, vec![4, 2, 1]);
+    }
+
+    #[test]
+    fn test_trajectory() {
+        let div_then_stop = Program::new(vec![Fraction::new(1_u64, 2_u64)]);
+        let states: Vec<u64> = div_then_stop.clone().trajectory(4).collect();
+        assert_eq!(states, vec![4, 2, 1]);
+        let states: Vec<u64> = div_then_stop.trajectory(3).collect();
+        assert_eq!(states, vec![3]);
+    }
+
+    #[test]
+    fn test_lazy_exec_indexed() {
+        let div_then_stop = Program::new(vec![Fraction::new(1_u64, 2_u64)]);
+        let steps: Vec<(usize, u64)> = div_then_stop.clone().lazy_exec_indexed(4).collect();
+        assert_eq!(steps, vec![(0, 4), (1, 2), (2, 1)]);
+        let steps: Vec<(usize, u64)> = div_then_stop.lazy_exec_indexed(3).collect();
+        assert_eq!(steps, vec![(0, 3)]);
+    }
+
+    #[test]
+    fn test_exec_with_limit() {
+        let div_then_stop = Program::new(vec![Fraction::new(1_u64, 2_u64)]);
+        assert_eq!(
+            div_then_stop.clone().exec_with_limit(8_u64, 10),
+            ExecOutcome::Halted(1_u64)
+        );
+        assert_eq!(
+            div_then_stop.clone().exec_with_limit(8_u64, 2),
+            ExecOutcome::LimitReached(2_u64)
+        );
+        // halting on the last allowed step, or with no steps at all, still
+        // counts, as with `halts_within`
+        assert_eq!(
+            div_then_stop.clone().exec_with_limit(2_u64, 1),
+            ExecOutcome::Halted(1_u64)
+        );
+        assert!(div_then_stop.clone().halts_within(2_u64, 1));
+        assert_eq!(
+            div_then_stop.clone().exec_with_limit(3_u64, 0),
+            ExecOutcome::Halted(3_u64)
+        );
+        assert_eq!(
+            div_then_stop.exec_with_limit(2_u64, 0),
+            ExecOutcome::LimitReached(2_u64)
+        );
+
+        // this runs forever, doubling each time
+        let forever = Program::new(vec![Fraction::new(2_u64, 1_u64)]);
+        assert_eq!(forever.exec_with_limit(1_u64, 5).into_state(), 32_u64);
+    }
+
+    #[test]
+    fn test_lazy_exec_checked() {
+        let prog = Program::new(vec![Fraction::new(1_u64, 2_u64)]);
+        let steps: Vec<_> = prog.lazy_exec_checked(4_u64).collect();
+        assert_eq!(steps, vec![Ok(2_u64), Ok(1_u64)]);
+
+        // multiplies by 2^32 each step, so the second step doesn't fit
+        let overflows = Program::new(vec![Fraction::new(1_u64 << 32, 1_u64)]);
+        let steps: Vec<_> = overflows.lazy_exec_checked(1_u64 << 16).collect();
+        assert_eq!(
+            steps,
+            vec![
+                Ok(1_u64 << 48),
+                Err(ExecError::Overflow {
+                    step: 1,
+                    fraction: 0
+                })
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_exec_detect_cycle() {
+        // 7 -> 2 -> 3 -> 2 -> ...
+        let ping_pong = Program::new(vec![
+            Fraction::new(3_u64, 2_u64),
+            Fraction::new(2_u64, 3_u64),
+            Fraction::new(2_u64, 7_u64),
+        ]);
+        assert_eq!(
+            ping_pong.clone().exec_detect_cycle(7_u64, 100),
+            CycleOutcome::Cycle {
+                start_index: 1,
+                period: 2,
+                states: vec![2_u64, 3_u64],
+            }
+        );
+        assert_eq!(
+            ping_pong.clone().exec_detect_cycle(7_u64, 2),
+            CycleOutcome::LimitReached(3_u64)
+        );
+        assert_eq!(
+            ping_pong.exec_detect_cycle(5_u64, 100),
+            CycleOutcome::Halted(5_u64)
+        );
+
+        // an identity fraction loops with period 1
+        let identity = Program::new(vec![Fraction::new(2_u64, 2_u64)]);
+        assert_eq!(
+            identity.exec_detect_cycle(4_u64, 100),
+            CycleOutcome::Cycle {
+                start_index: 0,
+                period: 1,
+                states: vec![4_u64],
+            }
+        );
+    }
+
+    #[test]
+    fn test_registers_used() {
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        // uses 2, 3, 5, 7, 11, and 13
+        assert_eq!(
+            mult_pb.registers_used(),
+            vec![0, 1, 2, 3, 4, 5].into_iter().collect()
+        );
+
+        let sparse = make_program(vec![17], vec![4]);
+        assert_eq!(sparse.registers_used(), vec![0, 6].into_iter().collect());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        assert_eq!(mult_pb.validate(), Ok(()));
+
+        let mut too_big = PrimeBasis::try_new(3).unwrap();
+        too_big.set_register(PRIMES.len() + 5, 1);
+        let prog = Program::new(vec![
+            Fraction::new(
+                PrimeBasis::try_new(2).unwrap(),
+                PrimeBasis::try_new(3).unwrap(),
+            ),
+            Fraction::new(PrimeBasis::try_new(1).unwrap(), too_big),
+        ]);
+        assert_eq!(
+            prog.validate(),
+            Err(ProgramError::RegisterOverflow {
+                fraction: 1,
+                register: PRIMES.len() + 5,
+            })
+        );
+    }
+
+    #[test]
+    fn test_step_evaluator() {
+        // 5/3, 2/5 on 9: the first fraction fires twice, then the second twice.
+        // `Itertools::step` is in scope here, so call the inherent method
+        // explicitly
+        let prog = Program::new(vec![Fraction::new(5_u64, 3), Fraction::new(2, 5)]);
+        let mut stepper = prog.step_evaluator(9);
+        assert_eq!(StepEvaluator::step(&mut stepper), Some((0, 15)));
+        assert_eq!(StepEvaluator::step(&mut stepper), Some((0, 25)));
+        assert_eq!(StepEvaluator::step(&mut stepper), Some((1, 10)));
+        assert_eq!(stepper.next(), Some(4));
+        assert_eq!(StepEvaluator::step(&mut stepper), None);
+        assert_eq!(StepEvaluator::step(&mut stepper), None);
+    }
+
+    #[test]
+    fn test_lazy_exec_with_rule() {
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        let input = PrimeBasis::try_new(2_u64.pow(3) * 3_u64.pow(2)).unwrap();
+        let with_rule: Vec<(usize, PrimeBasis)> =
+            mult_pb.clone().lazy_exec_with_rule(input.clone()).collect();
+        let states: Vec<PrimeBasis> = with_rule.iter().map(|(_, s)| s.clone()).collect();
+        assert_eq!(states, mult_pb.clone().lazy_exec(input).collect::<Vec<_>>());
+        for (i, state) in with_rule.iter().skip(1).enumerate() {
+            // each state is the previous state times the fraction that fired
+            let (_, prev) = &with_rule[i];
+            let frac = &mult_pb.fractions()[state.0];
+            assert_eq!(
+                prev.clone() * frac.num().clone(),
+                state.1.clone() * frac.denom().clone()
+            );
+        }
+        assert_eq!(with_rule[0].0, 4);
+    }
+
+    #[test]
+    fn test_coverage() {
+        // 3 * 2 = 6, so 11/2 fires twice and 455/33 fires 6 times
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        let input = PrimeBasis::try_new(2_u64.pow(2) * 3_u64.pow(3)).unwrap();
+        let counts = mult_pb.clone().coverage(input.clone(), 1000);
+        assert_eq!(counts.len(), 6);
+        assert_eq!(counts[0], 6);
+        assert_eq!(counts[4], 2);
+        let total = mult_pb.clone().lazy_exec(input.clone()).count();
+        assert_eq!(counts.iter().sum::<usize>(), total);
+
+        // a bounded run only counts the steps it took
+        assert_eq!(mult_pb.coverage(input, 1), vec![0, 0, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_builder() {
+        let prog = Program::builder()
+            .frac(5_u64, 3)
+            .frac(2, 5)
+            .build()
+            .unwrap();
+        assert_eq!(
+            prog,
+            Program::new(vec![Fraction::new(5, 3), Fraction::new(2, 5)])
+        );
+        assert_eq!(
+            ProgramBuilder::<u64>::new().build(),
+            Err(ProgramError::EmptyProgram)
+        );
+        assert_eq!(
+            Program::builder().frac(5_u64, 3).frac(0, 5).build(),
+            Err(ProgramError::ZeroInFraction { fraction: 1 })
+        );
+    }
+
+    #[test]
+    fn test_try_from_pairs() {
+        let prog = Program::try_from(&[(455, 33), (11, 13), (1, 11)][..]).unwrap();
+        assert_eq!(
+            prog,
+            Program::new(vec![
+                Fraction::new(455_u64, 33),
+                Fraction::new(11, 13),
+                Fraction::new(1, 11)
+            ])
+        );
+        assert_eq!(
+            Program::try_from(&[(3, 2), (5, 0)][..]),
+            Err(ProgramError::ZeroInFraction { fraction: 1 })
+        );
+        assert_eq!(Program::try_from(&[][..]), Err(ProgramError::EmptyProgram));
+    }
+
+    #[test]
+    fn test_from_u64_fractions() {
+        let prog = Program::from_u64_fractions(&[(455, 33), (11, 13)]).unwrap();
+        assert_eq!(prog, make_program(vec![455, 11], vec![33, 13]));
+        assert_eq!(
+            Program::from_u64_fractions(&[(3, 2), (7927, 1)]),
+            Err(ProgramError::Basis {
+                fraction: 1,
+                source: primebasis::Error::RegisterOverflow(7927),
+            })
+        );
+        assert_eq!(
+            Program::from_u64_fractions(&[(3, 0)]),
+            Err(ProgramError::ZeroInFraction { fraction: 0 })
+        );
+        assert_eq!(
+            Program::from_u64_fractions(&[]),
+            Err(ProgramError::EmptyProgram)
+        );
+    }
+
+    #[test]
+    fn test_halts_within() {
+        // count how many steps computing 3 * 2 takes
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        let input = PrimeBasis::try_new(2_u64.pow(3) * 3_u64.pow(2)).unwrap();
+        let steps = mult_pb.clone().lazy_exec(input.clone()).count();
+        assert!(mult_pb.clone().halts_within(input.clone(), steps));
+        assert!(mult_pb.clone().halts_within(input.clone(), steps + 10));
+        assert!(!mult_pb.clone().halts_within(input.clone(), steps - 1));
+        assert!(!mult_pb.clone().halts_within(input.clone(), 0));
+
+        // agrees with `exec_with_limit` on either side of the last step
+        for budget in steps - 1..=steps + 1 {
+            let halted = matches!(
+                mult_pb.clone().exec_with_limit(input.clone(), budget),
+                ExecOutcome::Halted(_)
+            );
+            assert_eq!(mult_pb.clone().halts_within(input.clone(), budget), halted);
+        }
+
+        // 2/1 never halts
+        let forever = make_program(vec![2], vec![1]);
+        assert!(!forever.halts_within(PrimeBasis::try_new(1).unwrap(), 1000));
+    }
+
+    #[test]
+    fn test_exec_ref() {
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        for &(a, b) in &[(0, 0), (3, 2), (5, 7)] {
+            let input = PrimeBasis::from(vec![a, b]);
+            assert_eq!(
+                mult_pb.exec_to_completion_ref(input.clone()),
+                PrimeBasis::from(vec![0, 0, a * b])
+            );
+            assert_eq!(
+                mult_pb.lazy_exec_ref(input.clone()).collect::<Vec<_>>(),
+                mult_pb.clone().lazy_exec(input).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_exec_many() {
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        let inputs: Vec<PrimeBasis> = (1..=4).map(|b| PrimeBasis::from(vec![2, b])).collect();
+        let outcomes = mult_pb.exec_many(inputs.clone(), 1000);
+        let expected: Vec<ExecOutcome<PrimeBasis>> = (1..=4)
+            .map(|b| ExecOutcome::Halted(PrimeBasis::from(vec![0, 0, 2 * b])))
+            .collect();
+        assert_eq!(outcomes, expected);
+
+        // a tight budget runs out for every input
+        for (outcome, input) in mult_pb.exec_many(inputs.clone(), 1).into_iter().zip(inputs) {
+            assert_eq!(outcome, mult_pb.clone().exec_with_limit(input, 1));
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_exec_many() {
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        let inputs: Vec<PrimeBasis> = (0..50)
+            .map(|i| PrimeBasis::from(vec![i % 7, i / 7]))
+            .collect();
+        for &max_steps in &[5, 1000] {
+            assert_eq!(
+                mult_pb.par_exec_many(&inputs, max_steps),
+                mult_pb.exec_many(inputs.clone(), max_steps)
+            );
+        }
+    }
+
+    #[test]
+    fn test_exec_until() {
+        // 2/1 doubles forever, so stop it once the state gets big
+        let forever = Program::new(vec![Fraction::new(2_u64, 1)]);
+        assert_eq!(
+            forever.clone().exec_until(3, |&n| n > 1000),
+            ExecOutcome::PredicateMet(1536)
+        );
+        assert_eq!(
+            forever.exec_until(5000, |&n| n > 1000),
+            ExecOutcome::PredicateMet(5000)
+        );
+
+        // stopping on a register bound with a program that does halt
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        let input = PrimeBasis::from(vec![3, 2]);
+        let outcome = mult_pb
+            .clone()
+            .exec_until(input.clone(), |pb| pb.register(2) >= 4);
+        assert_eq!(outcome.into_state().register(2), 4);
+        assert_eq!(
+            mult_pb.exec_until(input, |pb| pb.register(2) > 6),
+            ExecOutcome::Halted(PrimeBasis::from(vec![0, 0, 6]))
+        );
+    }
+
+    #[test]
+    fn test_exec_greedy_all() {
+        // with 3/2, 5/3, each step turns a 2 into a 3 and then into a 5, while
+        // canonical Fractran takes two steps to do the same
+        let prog = Program::new(vec![Fraction::new(3_u64, 2), Fraction::new(5, 3)]);
+        assert_eq!(
+            prog.clone().exec_greedy_all(8, 100),
+            ExecOutcome::Halted(125)
+        );
+        assert_eq!(
+            prog.clone().exec_greedy_all(8, 2),
+            ExecOutcome::LimitReached(50)
+        );
+        assert_eq!(prog.clone().exec_greedy_all(7, 100), ExecOutcome::Halted(7));
+        assert_eq!(prog.exec_with_limit(8, 2), ExecOutcome::LimitReached(18));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_exec_random() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        // each step turns a 2 into either a 3 or a 5
+        let prog = make_program(vec![3, 5], vec![2, 2]);
+        let input = PrimeBasis::from(vec![20]);
+        let run = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            prog.clone().exec_random(input.clone(), &mut rng, 100)
+        };
+        let outcome = run(42);
+        assert_eq!(outcome, run(42));
+        let state = outcome.into_state();
+        assert_eq!(state.register(0), 0);
+        assert_eq!(state.register(1) + state.register(2), 20);
+        // with 20 coin flips, both fractions almost surely fire
+        assert!(state.register(1) > 0 && state.register(2) > 0);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        match prog.exec_random(input, &mut rng, 5) {
+            ExecOutcome::LimitReached(state) => assert_eq!(state.register(0), 15),
+            outcome => panic!("expected to run out of steps, got {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_fused() {
+        let prog = Program::new(vec![Fraction::new(1_u64, 2)]);
+        let mut iter = prog.clone().lazy_exec(4);
+        assert_eq!(iter.size_hint(), (0, None));
+        assert_eq!(iter.by_ref().collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+
+        let mut checked = prog.lazy_exec_checked(2);
+        assert_eq!(checked.next(), Some(Ok(1)));
+        assert_eq!(checked.next(), None);
+        assert_eq!(checked.size_hint(), (0, Some(0)));
+        assert_eq!(checked.next(), None);
+    }
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        let input = PrimeBasis::from(vec![3, 4]);
+        let all: Vec<PrimeBasis> = mult_pb.lazy_exec_ref(input.clone()).collect();
+
+        let mut first = mult_pb.evaluator(input);
+        let start: Vec<PrimeBasis> = first.by_ref().take(10).collect();
+        let checkpoint = first.checkpoint();
+        assert_eq!(checkpoint.state, start[9]);
+        assert!(!checkpoint.finished);
+        drop(first);
+
+        let rest: Vec<PrimeBasis> = mult_pb.resume(checkpoint).collect();
+        assert_eq!([start, rest].concat(), all);
+
+        // a finished run stays finished, even from a state that could step
+        let done = Checkpoint {
+            state: all[0].clone(),
+            finished: true,
+        };
+        assert_eq!(mult_pb.resume(done).next(), None);
+    }
+
+    #[test]
+    fn test_current_and_is_finished() {
+        let prog = Program::new(vec![Fraction::new(1_u64, 3), Fraction::new(3, 2)]);
+        let mut evaluator = prog.evaluator(4);
+        assert_eq!(*evaluator.current(), 4);
+        assert_eq!(evaluator.next(), Some(6));
+        // peeking doesn't advance the run
+        assert_eq!(*evaluator.current(), 6);
+        assert_eq!(*evaluator.current(), 6);
+        assert!(!evaluator.is_finished());
+
+        let rest: Vec<u64> = evaluator.by_ref().collect();
+        assert_eq!(rest, vec![2, 3, 1]);
+        assert_eq!(*evaluator.current(), 1);
+        assert!(evaluator.is_finished());
+    }
+
+    #[test]
+    fn test_max_state() {
+        // each 2 becomes a 3 that 1/3 then discards, so the state peaks after
+        // the first step: 8, 12, 4, 6, 2, 3, 1
+        let prog = Program::new(vec![Fraction::new(1_u64, 3), Fraction::new(3, 2)]);
+        assert_eq!(prog.clone().max_state(8, 100), 12);
+        assert_eq!(prog.clone().max_state(8, 0), 8);
+        assert_eq!(prog.max_state(1, 100), 1);
+
+        // the multiplication program's states are much larger than its output
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        let input = PrimeBasis::from(vec![3, 4]);
+        let max = mult_pb.clone().max_state(input.clone(), 1000);
+        assert!(max > mult_pb.exec_to_completion(input.clone()));
+        assert!(max > input);
+    }
+
+    #[test]
+    fn test_strip_halt() {
+        let prog = Program::new(vec![
+            Fraction::new(1_u64, 2),
+            Fraction::new(1, 1),
+            Fraction::new(5, 3),
+        ]);
+        assert!(!prog.clone().halts_within(8, 100));
+        let stripped = prog.strip_halt().unwrap();
+        assert_eq!(
+            stripped,
+            Program::new(vec![Fraction::new(1_u64, 2), Fraction::new(5, 3)])
+        );
+        assert_eq!(stripped.exec_to_completion(24), 5);
+
+        let all_identity = Program::new(vec![Fraction::new(3_u64, 3)]);
+        assert_eq!(all_identity.strip_halt(), Err(ProgramError::EmptyProgram));
+    }
+
+    #[test]
+    fn test_dead_fractions() {
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        assert_eq!(mult_pb.dead_fractions(), vec![]);
+
+        // 55/1 always applies, but as in the README program it comes last
+        let readme = make_program(vec![15, 55], vec![2, 1]);
+        assert_eq!(readme.dead_fractions(), vec![]);
+
+        let prog = make_program(vec![3, 10, 5, 7], vec![2, 5, 3, 1]);
+        assert_eq!(prog.dead_fractions(), vec![2, 3]);
+        let after_one = make_program(vec![2, 3, 5], vec![1, 2, 3]);
+        assert_eq!(after_one.dead_fractions(), vec![1, 2]);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_reduced() {
+        let prog = make_program(vec![910, 11, 2], vec![66, 13, 22]);
+        let reduced = prog.clone().reduced();
+        assert_eq!(reduced, make_program(vec![455, 11, 1], vec![33, 13, 11]));
+        let input = PrimeBasis::from(vec![0, 3, 0, 0, 1]);
+        assert_eq!(
+            prog.lazy_exec(input.clone()).collect::<Vec<_>>(),
+            reduced.lazy_exec(input).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_value_eq() {
+        let prog = Program::new(vec![Fraction::new(3_u64, 2), Fraction::new(5, 7)]);
+        let scaled = Program::new(vec![Fraction::new(6_u64, 4), Fraction::new(15, 21)]);
+        assert_eq!(prog, scaled);
+        assert!(prog.value_eq(&scaled));
+        assert!(scaled.value_eq(&prog));
+
+        let swapped = Program::new(vec![Fraction::new(5_u64, 7), Fraction::new(3, 2)]);
+        assert!(!prog.value_eq(&swapped));
+        let shorter = Program::new(vec![Fraction::new(3_u64, 2)]);
+        assert!(!prog.value_eq(&shorter));
+    }
+
+    #[test]
+    fn test_optimize() {
+        // 6/4 reduces to 3/2; 5/2 and the repeated 3/2 can never fire after it,
+        // and nothing after 7/1 can
+        let prog = Program::new(vec![
+            Fraction::new(6_u64, 4),
+            Fraction::new(5, 2),
+            Fraction::new(11, 3),
+            Fraction::new(3, 2),
+            Fraction::new(7, 1),
+            Fraction::new(13, 5),
+        ]);
+        let optimized = prog.clone().optimize();
+        assert_eq!(
+            optimized,
+            Program::new(vec![
+                Fraction::new(3_u64, 2),
+                Fraction::new(11, 3),
+                Fraction::new(7, 1),
+            ])
+        );
+        for input in 1..100_u64 {
+            assert_eq!(
+                prog.exec_with_limit_ref(input, 20),
+                optimized.exec_with_limit_ref(input, 20)
+            );
+        }
+
+        // the README program is already as small as this can make it
+        let readme = make_program(
+            vec![17, 78, 19, 23, 29, 77, 95, 77, 1, 11, 13, 15, 15, 55],
+            vec![91, 85, 51, 38, 33, 29, 23, 19, 17, 13, 11, 14, 2, 1],
+        );
+        let two = PrimeBasis::from(vec![1]);
+        let optimized = readme.clone().optimize();
+        assert_eq!(optimized, readme);
+        assert_eq!(
+            optimized
+                .lazy_exec(two.clone())
+                .take(5000)
+                .collect::<Vec<_>>(),
+            readme.lazy_exec(two).take(5000).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_reachable_states() {
+        // halts: 8, 4, 2, 1
+        let halve = Program::new(vec![Fraction::new(1_u64, 2)]);
+        assert_eq!(halve.clone().reachable_states(8, 100), vec![8, 4, 2, 1]);
+        assert_eq!(halve.reachable_states(8, 2), vec![8, 4]);
+
+        // loops: 2 -> 3 -> 2 -> ...
+        let swap = Program::new(vec![Fraction::new(3_u64, 2), Fraction::new(2, 3)]);
+        assert_eq!(swap.clone().reachable_states(2, 100), vec![2, 3]);
+        assert_eq!(swap.reachable_states(2, 0), Vec::<u64>::new());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_trace_to_dot() {
+        // 3/2, then 1/3 ends at 1
+        let prog = make_program(vec![3, 1], vec![2, 3]);
+        let dot = prog.trace_to_dot(PrimeBasis::from(vec![1]), 100);
+        assert_eq!(
+            dot,
+            "digraph trace {\n\
+             \x20   s0 [label=\"r0=1\"];\n\
+             \x20   s1 [label=\"r1=1\"];\n\
+             \x20   s2 [label=\"1\"];\n\
+             \x20   s0 -> s1 [label=\"0\"];\n\
+             \x20   s1 -> s2 [label=\"1\"];\n\
+             }\n"
+        );
+
+        // 3/2, 2/3 loops back to the start
+        let cycle = make_program(vec![3, 2], vec![2, 3]);
+        let dot = cycle.trace_to_dot(PrimeBasis::from(vec![1]), 100);
+        assert!(dot.contains("s0 -> s1 [label=\"0\"];"));
+        assert!(dot.contains("s1 -> s0 [label=\"1\"];"));
+        assert_eq!(dot.matches("->").count(), 2);
+
+        // 3/3 leaves the state alone, so it loops on itself
+        let identity = Program::new(vec![Fraction::new(
+            PrimeBasis::from(vec![0, 1]),
+            PrimeBasis::from(vec![0, 1]),
+        )]);
+        let dot = identity.trace_to_dot(PrimeBasis::from(vec![0, 1]), 100);
+        assert!(dot.contains("s0 -> s0 [label=\"0\"];"));
+        assert_eq!(dot.matches("->").count(), 1);
+
+        // 2/1 never repeats a state, so the trace stops at `max_steps`
+        let counter = make_program(vec![2], vec![1]);
+        let dot = counter.trace_to_dot(PrimeBasis::from(vec![]), 5000);
+        assert_eq!(dot.matches("->").count(), 5000);
+    }
+
+    #[test]
+    fn test_register_profile() {
+        // 455/33, 11/13, 1/11, 3/7, 11/2, 1/3 multiplies r0 by r1 into r2
+        let prog = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        let profile = prog
+            .clone()
+            .register_profile(PrimeBasis::from(vec![2, 3]), 1000);
+        assert_eq!(profile.max_exponents, vec![2, 3, 6, 3, 1, 1]);
+        assert_eq!(profile.max_nonzero, 5);
+        assert_eq!(
+            profile.steps,
+            prog.clone().lazy_exec(PrimeBasis::from(vec![2, 3])).count()
+        );
+
+        let cut_short = prog.register_profile(PrimeBasis::from(vec![2, 3]), 0);
+        assert_eq!(
+            cut_short,
+            RegisterProfile {
+                max_exponents: vec![2, 3],
+                max_nonzero: 2,
+                steps: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_lazy_exec_annotated() {
+        let prog = make_program(vec![3, 1], vec![2, 3]);
+        let annotated: Vec<(u64, bool)> = prog
+            .lazy_exec_annotated(PrimeBasis::from(vec![2]))
+            .map(|(state, changed)| (state.value(), changed))
+            .collect();
+        assert_eq!(annotated, vec![(6, true), (9, true), (3, true), (1, true)]);
+
+        // 1/1 applies on every step but never changes anything
+        let stuck = make_program(vec![1], vec![1]);
+        let annotated: Vec<(PrimeBasis, bool)> = stuck
+            .lazy_exec_annotated(PrimeBasis::from(vec![1]))
+            .take(3)
+            .collect();
+        assert_eq!(annotated, vec![(PrimeBasis::from(vec![1]), false); 3]);
+    }
+
+    #[test]
+    fn test_lazy_exec_with_end() {
+        let prog = make_program(vec![3, 1], vec![2, 3]);
+        let pb = |n| PrimeBasis::try_new(n).unwrap();
+        let events: Vec<Event<PrimeBasis>> = prog.clone().lazy_exec_with_end(pb(4)).collect();
+        assert_eq!(
+            events,
+            vec![
+                Event::Step(pb(6)),
+                Event::Step(pb(9)),
+                Event::Step(pb(3)),
+                Event::Step(pb(1)),
+                Event::Halted(pb(1)),
+            ]
+        );
+
+        // halting right away still reports the input
+        let mut halts_now = prog.clone().lazy_exec_with_end(pb(5));
+        assert_eq!(halts_now.next(), Some(Event::Halted(pb(5))));
+        assert_eq!(halts_now.next(), None);
+        assert_eq!(halts_now.next(), None);
+
+        // a truncated run has no end marker
+        assert!(!prog
+            .lazy_exec_with_end(pb(4))
+            .take(2)
+            .any(|event| event.is_halted()));
+    }
+
+    #[test]
+    fn test_output_filter() {
+        let primes = make_program(
+            vec![17, 78, 19, 23, 29, 77, 95, 77, 1, 11, 13, 15, 15, 55],
+            vec![91, 85, 51, 38, 33, 29, 23, 19, 17, 13, 11, 14, 2, 1],
+        );
+        let outputs: Vec<u32> = primes
+            .output_filter(PrimeBasis::from(vec![1]), |state| state.is_power_of(2))
+            .take(5)
+            .collect();
+        assert_eq!(outputs, vec![2, 3, 5, 7, 11]);
+
+        // the iterator ends when the program halts
+        let halts = make_program(vec![3, 1], vec![2, 3]);
+        let odd: Vec<u64> = halts
+            .output_filter(PrimeBasis::from(vec![2]), |state| {
+                Some(state.value()).filter(|v| v % 2 == 1)
+            })
+            .collect();
+        assert_eq!(odd, vec![9, 3, 1]);
+    }
+
+    #[test]
+    fn test_exec_with_policy() {
+        // 3^40 fits in a u64, but 3^80 doesn't
+        let big = 3_u64.pow(40);
+        let prog = Program::new(vec![Fraction::new(1_u64, 7), Fraction::new(big, 1)]);
+        assert_eq!(
+            prog.clone().exec_with_policy(7, OverflowPolicy::Error, 10),
+            Err(ExecError::Overflow {
+                step: 2,
+                fraction: 1
+            })
+        );
+        assert_eq!(
+            prog.clone().exec_with_policy(1, OverflowPolicy::Wrap, 2),
+            Ok(ExecOutcome::LimitReached(big.wrapping_mul(big)))
+        );
+        assert_eq!(
+            prog.clone()
+                .exec_with_policy(1, OverflowPolicy::Saturate, 2),
+            Ok(ExecOutcome::LimitReached(u64::MAX))
+        );
+
+        // policies only differ once something overflows
+        let halts = Program::new(vec![Fraction::new(3_u64, 2)]);
+        for policy in [
+            OverflowPolicy::Wrap,
+            OverflowPolicy::Saturate,
+            OverflowPolicy::Error,
+        ] {
+            assert_eq!(
+                prog.clone().exec_with_policy(1, policy, 1),
+                Ok(ExecOutcome::LimitReached(big))
+            );
+            assert_eq!(
+                halts.clone().exec_with_policy(8, policy, 10),
+                Ok(ExecOutcome::Halted(27))
+            );
+        }
+    }
+
+    #[test]
+    fn test_then() {
+        let first = make_program(vec![3], vec![2]);
+        let second = make_program(vec![5], vec![3]);
+        let both = make_program(vec![3, 5], vec![2, 3]);
+        assert_eq!(first.clone().then(second.clone()), both);
+        assert_eq!(first + second, both);
+    }
+
+    #[test]
+    fn test_compose_via_registers() {
+        // 3/2 moves r0 to r1, and 2/3 moves it back: concatenated they loop
+        let forward = make_program(vec![3], vec![2]);
+        let back = make_program(vec![2], vec![3]);
+        let input = PrimeBasis::from(vec![3]);
+        assert!(!forward
+            .clone()
+            .then(back.clone())
+            .halts_within(input.clone(), 1000));
+
+        let (composed, guard) = forward.compose_via_registers(back);
+        assert_eq!(guard, 2);
+        let mut start = input.clone();
+        start.set_register(guard, 1);
+        assert_eq!(composed.exec_to_completion(start), input);
+
+        // the second program runs on the first program's output
+        let to_r1 = make_program(vec![3], vec![2]);
+        let double = make_program(vec![25], vec![3]);
+        let (composed, guard) = to_r1.compose_via_registers(double);
+        let mut start = PrimeBasis::from(vec![4]);
+        start.set_register(guard, 1);
+        assert_eq!(
+            composed.exec_to_completion(start),
+            PrimeBasis::from(vec![0, 0, 8])
+        );
+    }
+
+    #[test]
+    fn test_shift_registers() {
+        // 455/33, 11/13, 1/11, 3/7, 11/2, 1/3 multiplies r0 by r1 into r2
+        let mult = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        let shifted = mult.clone().shift_registers(3);
+        assert_eq!(shifted.registers_used(), (3..9).collect());
+        assert_eq!(
+            shifted.fractions()[4],
+            Fraction::new(
+                PrimeBasis::from(vec![0, 0, 0, 0, 0, 0, 0, 1]),
+                PrimeBasis::from(vec![0, 0, 0, 1])
+            )
+        );
+
+        let input = PrimeBasis::from(vec![3, 4]);
+        let output = mult.exec_to_completion(input.clone());
+        // the shifted program leaves the low registers alone
+        let mut shifted_input = input.clone().shift_registers(3);
+        shifted_input.set_register(0, 7);
+        let mut expected = output.shift_registers(3);
+        expected.set_register(0, 7);
+        assert_eq!(shifted.exec_to_completion(shifted_input), expected);
+    }
+
+    #[test]
+    fn test_map_fractions() {
+        let prog = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        assert_eq!(prog.clone().map_fractions(|frac| frac), prog);
+
+        // flipping every fraction
+        let flipped =
+            prog.map_fractions(|frac| Fraction::new(frac.denom().clone(), frac.num().clone()));
+        assert_eq!(
+            flipped,
+            make_program(vec![33, 13, 11, 7, 2, 3], vec![455, 11, 1, 3, 11, 1])
+        );
+    }
+
+    #[test]
+    fn test_into_prime_basis() {
+        let prog = Program::try_from(&[(455, 33), (11, 13), (1, 11), (3, 7), (11, 2), (1, 3)][..])
+            .unwrap();
+        let converted = prog.into_prime_basis().unwrap();
+        assert_eq!(
+            converted,
+            make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3])
+        );
+        assert_eq!(
+            converted.exec_to_completion(PrimeBasis::from(vec![3, 4])),
+            PrimeBasis::from(vec![0, 0, 12])
+        );
+
+        // 7927 is the first prime past PRIMES
+        let too_big = Program::try_from(&[(3, 2), (7927, 5)][..]).unwrap();
+        assert_eq!(
+            too_big.into_prime_basis(),
+            Err(ProgramError::Basis {
+                fraction: 1,
+                source: primebasis::Error::RegisterOverflow(7927)
+            })
+        );
+    }
+
+    #[test]
+    fn test_convert() {
+        let small = Program::try_from(&[(455, 33), (11, 13), (1, 11), (3, 7), (11, 2), (1, 3)][..])
+            .unwrap();
+        let basis: Program<PrimeBasis> = small.clone().convert().unwrap();
+        assert_eq!(basis, small.clone().into_prime_basis().unwrap());
+
+        let small_copy = small.clone();
+        let wide: Program<u128> = small.clone().convert().unwrap();
+        assert_eq!(
+            wide.clone()
+                .exec_to_completion(2_u128.pow(3) * 3_u128.pow(4)),
+            5_u128.pow(12)
+        );
+        let fixed: Program<FixedBasis<6>> = basis.clone().convert().unwrap();
+        assert_eq!(
+            fixed
+                .exec_to_completion(FixedBasis::from([3, 4, 0, 0, 0, 0]))
+                .register(2),
+            12
+        );
+
+        // back again
+        assert_eq!(basis.convert::<u64>(), Ok(small.clone()));
+
+        // 455 doesn't fit in a u8, and 13 needs more than 4 registers
+        assert!(matches!(
+            small.clone().convert::<u8>(),
+            Err(ConvertError::Target { fraction: 0, .. })
+        ));
+        assert_eq!(
+            small.convert::<FixedBasis<4>>(),
+            Err(ConvertError::Target {
+                fraction: 0,
+                source: primebasis::Error::RegisterOverflow(455)
+            })
+        );
+
+        // numbers too large for a u64 are refused instead of wrapping
+        let huge = Program::new(vec![
+            Fraction::new(PrimeBasis::from(vec![0, 1]), PrimeBasis::from(vec![1])),
+            Fraction::new(PrimeBasis::from(vec![64]), PrimeBasis::from(vec![0, 1])),
+        ]);
+        assert_eq!(
+            huge.convert::<u64>(),
+            Err(ConvertError::TooLarge { fraction: 1 })
+        );
+        let wide_huge = Program::new(vec![Fraction::new(1_u128 << 64, 3)]);
+        assert_eq!(
+            wide_huge.convert::<PrimeBasis>(),
+            Err(ConvertError::TooLarge { fraction: 0 })
+        );
+        assert_eq!(wide.convert::<u64>(), Ok(small_copy));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_exec_timed() {
+        // 3/2 fires twice from 4, then 1/3 fires twice
+        let prog = make_program(vec![3, 1], vec![2, 3]);
+        let stats = prog.clone().exec_timed(PrimeBasis::from(vec![2]), 100);
+        assert_eq!(stats.outcome, ExecOutcome::Halted(PrimeBasis::from(vec![])));
+        assert_eq!(stats.steps, 4);
+        assert_eq!(stats.mean_fraction_index, 0.5);
+        assert!(stats.steps_per_second() >= 0.0);
+
+        let stats = prog.clone().exec_timed(PrimeBasis::from(vec![2]), 1);
+        assert_eq!(
+            stats.outcome,
+            ExecOutcome::LimitReached(PrimeBasis::from(vec![1, 1]))
+        );
+        assert_eq!(stats.steps, 1);
+        assert_eq!(stats.mean_fraction_index, 0.0);
+
+        let stats = prog.exec_timed(PrimeBasis::from(vec![0, 0, 1]), 100);
+        assert_eq!(
+            stats.outcome,
+            ExecOutcome::Halted(PrimeBasis::from(vec![0, 0, 1]))
+        );
+        assert_eq!(stats.steps, 0);
+    }
+
+    #[test]
+    fn test_exec_with_scan() {
+        // from 4: restarting fires 3/2 twice and then 5/3, but continuing past 3/2
+        // only gets back to it after 5/3 and 7/5
+        let prog = make_program(vec![3, 5, 7, 2], vec![2, 3, 5, 1]);
+        let mut restart = prog
+            .clone()
+            .step_evaluator(PrimeBasis::from(vec![2]))
+            .with_scan_mode(ScanMode::RestartFromTop);
+        let fired: Vec<usize> = (0..5)
+            .filter_map(|_| StepEvaluator::step(&mut restart).map(|(i, _)| i))
+            .collect();
+        assert_eq!(fired, vec![0, 0, 1, 1, 2]);
+
+        let mut cont = prog
+            .clone()
+            .step_evaluator(PrimeBasis::from(vec![2]))
+            .with_scan_mode(ScanMode::ContinueNext);
+        let fired: Vec<usize> = (0..5)
+            .filter_map(|_| StepEvaluator::step(&mut cont).map(|(i, _)| i))
+            .collect();
+        assert_eq!(fired, vec![0, 1, 2, 3, 0]);
+
+        // halting still needs every fraction to fail
+        let halts = make_program(vec![3, 1], vec![2, 3]);
+        for scan in [ScanMode::RestartFromTop, ScanMode::ContinueNext] {
+            assert_eq!(
+                halts
+                    .clone()
+                    .exec_with_scan(PrimeBasis::from(vec![2]), scan, 100),
+                ExecOutcome::Halted(PrimeBasis::from(vec![]))
+            );
+        }
+        assert_eq!(
+            prog.clone()
+                .exec_with_scan(PrimeBasis::from(vec![2]), ScanMode::RestartFromTop, 7),
+            prog.exec_with_limit(PrimeBasis::from(vec![2]), 7)
+        );
+    }
+
+    #[test]
+    fn test_exec_with_register_cap() {
+        // 455/33, 11/13, 1/11, 3/7, 11/2, 1/3 multiplies r0 by r1 into r2
+        let mult = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        let input = PrimeBasis::from(vec![3, 4]);
+        assert_eq!(
+            mult.clone()
+                .exec_with_register_cap(input.clone(), 100, 1000),
+            ExecOutcome::Halted(PrimeBasis::from(vec![0, 0, 12]))
+        );
+        // the product can't count past 5
+        assert_eq!(
+            mult.clone().exec_with_register_cap(input.clone(), 5, 1000),
+            ExecOutcome::Halted(PrimeBasis::from(vec![0, 0, 5]))
+        );
+        // the input is capped too, so this multiplies 2 by 2 and caps the 4
+        assert_eq!(
+            mult.exec_with_register_cap(input, 2, 1000),
+            ExecOutcome::Halted(PrimeBasis::from(vec![0, 0, 2]))
+        );
+    }
+
+    #[test]
+    fn test_enumerate_candidates() {
+        // 1/1, 1/2, 2/1 are the only fractions in lowest terms up to 2/2
+        let programs: Vec<String> = Program::enumerate_candidates(2, 2, 2)
+            .map(|prog| prog.to_string())
+            .collect();
+        assert_eq!(programs.len(), 9);
+        assert_eq!(programs[0], "1/1, 1/1");
+        assert!(programs.contains(&"2/1, 1/2".to_string()));
+        assert_eq!(
+            programs.iter().collect::<BTreeSet<_>>().len(),
+            programs.len()
+        );
+
+        assert_eq!(Program::enumerate_candidates(3, 4, 1).count(), 9);
+        assert_eq!(Program::enumerate_candidates(3, 4, 0).count(), 0);
+        // there are 63 fractions in lowest terms up to 10/10, so this doesn't
+        // build all 63^30 programs
+        let mut huge = Program::enumerate_candidates(10, 10, 30);
+        assert_eq!(huge.next().unwrap().len(), 30);
+    }
+
+    #[test]
+    fn test_canonical_key() {
+        let prog = make_program(vec![3, 1], vec![2, 3]);
+        let key = prog.canonical_key();
+        let pb = |n| PrimeBasis::try_new(n).unwrap();
+        assert_eq!(key.fractions(), &[(pb(3), pb(2)), (pb(1), pb(3))]);
+
+        // the same fractions written differently, with trailing zero registers
+        let padded = Program::new(vec![
+            Fraction::new(
+                PrimeBasis {
+                    exps: vec![0, 2, 0],
+                },
+                PrimeBasis {
+                    exps: vec![1, 1, 0, 0],
+                },
+            ),
+            Fraction::new(
+                PrimeBasis { exps: vec![0, 0] },
+                PrimeBasis {
+                    exps: vec![0, 1, 0],
+                },
+            ),
+        ]);
+        assert_eq!(padded.canonical_key(), key);
+
+        let keys: BTreeSet<CanonicalKey<u64>> = [
+            vec![(3, 2), (1, 3)],
+            vec![(6, 4), (2, 6)],
+            vec![(1, 3), (3, 2)],
+        ]
+        .iter()
+        .map(|pairs| Program::try_from(&pairs[..]).unwrap().canonical_key())
+        .collect();
+        assert_eq!(keys.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_exec_with_timeout() {
+        let halts = make_program(vec![3, 1], vec![2, 3]);
+        assert_eq!(
+            halts.exec_with_timeout(PrimeBasis::from(vec![10]), Duration::from_secs(60)),
+            ExecOutcome::Halted(PrimeBasis::from(vec![]))
+        );
+
+        // 2/1 runs forever, so only the clock stops it
+        let forever = make_program(vec![2], vec![1]);
+        let outcome =
+            forever.exec_with_timeout(PrimeBasis::from(vec![]), Duration::from_millis(10));
+        match outcome {
+            ExecOutcome::TimedOut(state) => assert!(state.register(0) >= 1024),
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_min_trigger_state() {
+        let prog = make_program(vec![1, 5, 7, 11, 13], vec![4, 6, 2, 8, 1]);
+        assert_eq!(prog.min_trigger_state(0), Some(PrimeBasis::from(vec![2])));
+        assert_eq!(
+            prog.min_trigger_state(1),
+            Some(PrimeBasis::from(vec![1, 1]))
+        );
+        // 4 and 6 both need more than a single 2
+        assert_eq!(prog.min_trigger_state(2), Some(PrimeBasis::from(vec![1])));
+        // any multiple of 8 is a multiple of 4, so 1/4 always fires first
+        assert_eq!(prog.min_trigger_state(3), None);
+        // 2 divides any state with a 2 in it, but 1 doesn't need one
+        assert_eq!(prog.min_trigger_state(4), Some(PrimeBasis::from(vec![])));
+        assert_eq!(prog.min_trigger_state(5), None);
+
+        // the result really does pick out the fraction
+        for i in 0..3 {
+            let state = prog.min_trigger_state(i).unwrap();
+            let (fired, _) = StepEvaluator::step(&mut prog.evaluator(state)).unwrap();
+            assert_eq!(fired, i);
+        }
+    }
+
+    fn assert_send_sync<I: Send + Sync>(_: &I) {}
+
+    #[test]
+    fn test_lazy_exec_send_sync() {
+        let prog = Program::new(vec![Fraction::new(1_u64, 3), Fraction::new(3, 2)]);
+        assert_send_sync(&prog.lazy_exec_ref(8));
+        assert_send_sync(&prog.evaluator(8));
+        assert_send_sync(&prog.clone().step_evaluator(8));
+        assert_send_sync(&prog.clone().lazy_exec_with_end(8));
+        assert_send_sync(&prog.clone().lazy_exec_annotated(8));
+        let mut run = prog.lazy_exec(8);
+        assert_send_sync(&run);
+        assert_eq!(run.next(), Some(12));
+
+        // a run started on one thread can finish on another
+        let rest = std::thread::spawn(move || run.collect::<Vec<u64>>())
+            .join()
+            .unwrap();
+        assert_eq!(rest, vec![4, 6, 2, 3, 1]);
+
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+        let run = mult_pb.lazy_exec(PrimeBasis::from(vec![3, 4]));
+        assert_send_sync(&run);
+        let out = std::thread::spawn(move || run.last().unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(out.register(2), 12);
+    }
+
+    #[test]
+    fn test_multiply() {
+        let mult_pb = make_program(vec![455, 11, 1, 3, 11, 1], vec![33, 13, 11, 7, 2, 3]);
+
+        // here, we input 2^3 * 3^2, so we should get 5^6
+        assert_eq!(
+            mult_pb
+                .exec_to_completion(PrimeBasis::try_new(72).unwrap())
+                .value(),
+            5_u64.pow(6)
+        );
+    }
+
+    /// Runs the prime-generating program from the README over `T`, starting
+    /// from 2, for at most `steps` steps or until the state overflows.
+    /// Collects the exponent of every power of two it outputs, which are
+    /// exactly the primes in order.
+    fn readme_primes<T: FractranNat>(make: impl Fn(u64) -> T, steps: usize) -> Vec<u32> {
+        let nums = vec![17, 78, 19, 23, 29, 77, 95, 77, 1, 11, 13, 15, 15, 55];
+        let denoms = vec![91, 85, 51, 38, 33, 29, 23, 19, 17, 13, 11, 14, 2, 1];
+        let fracs: Vec<Fraction<T>> = nums
+            .into_iter()
+            .zip(denoms)
+            .map(|(num, denom)| Fraction::new(make(num), make(denom)))
+            .collect();
+
+        Program::new(fracs)
+            .lazy_exec_checked(make(2))
+            .take(steps)
+            .map_while(Result::ok)
+            .filter_map(|out| out.is_power_of(2))
+            .collect()
+    }
+
+    #[test]
+    fn test_readme_primes_u128() {
+        // no step limit: the run ends at the first step that would overflow a
+        // u128, by which point it has output every prime up to 23
+        assert_eq!(
+            readme_primes(u128::from, usize::MAX),
+            vec![2, 3, 5, 7, 11, 13, 17, 19, 23]
+        );
+    }
+
+    #[test]
+    fn test_readme_primes() {
+        assert_eq!(readme_primes(|n| n, 2000), vec![2, 3, 5, 7]);
+    }
+
+    #[test]
+    fn test_readme_primes_2e() {
+        assert_eq!(
+            readme_primes(|n| PrimeBasis::try_new(n).unwrap(), 100_000),
+            vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37]
+        );
+    }
+
+    #[test]
+    fn test_readme_primes_fixed() {
+        // the program only uses the first 10 primes
+        assert_eq!(
+            readme_primes(|n| FixedBasis::<10>::try_new(n).unwrap(), 100_000),
+            vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37]
+        );
     }
 }
@@ -0,0 +1,169 @@
+//! Provides a register-machine form of Fractran programs over `PrimeBasis`,
+//! where each fraction becomes an instruction that checks, decrements, and
+//! increments registers (prime exponents) directly.
+
+use alloc::{vec, vec::Vec};
+
+use super::primebasis::PrimeBasis;
+use super::program::Program;
+
+/// A single register-machine instruction, translated from one fraction. If
+/// every register in `decrements` is at least the given amount, the instruction
+/// applies: those registers are decreased and the registers in `increments` are
+/// increased. Registers are listed in increasing order, and no register appears
+/// in both lists.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RegisterInstruction {
+    /// The `(register, amount)` pairs that must be available and are taken away.
+    pub decrements: Vec<(usize, u32)>,
+    /// The `(register, amount)` pairs that are added if the instruction applies.
+    pub increments: Vec<(usize, u32)>,
+}
+
+impl RegisterInstruction {
+    /// Returns `true` if every register to decrement has enough in it.
+    pub fn applies(&self, registers: &PrimeBasis) -> bool {
+        self.decrements
+            .iter()
+            .all(|&(reg, amount)| registers.register(reg) >= amount)
+    }
+}
+
+/// A register machine equivalent to a Fractran program: a list of instructions
+/// where, on every step, the first one that applies is run, and the machine
+/// halts once none of them apply.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RegisterMachine {
+    /// The instructions, in the same order as the fractions they came from.
+    instructions: Vec<RegisterInstruction>,
+}
+
+impl RegisterMachine {
+    /// Returns the instructions of this machine, in order.
+    pub fn instructions(&self) -> &[RegisterInstruction] {
+        &self.instructions
+    }
+
+    /// Runs the first instruction that applies to `registers`, returning its
+    /// index, or `None` without changing anything if no instruction applies and
+    /// the machine has halted.
+    pub fn step(&self, registers: &mut PrimeBasis) -> Option<usize> {
+        let (i, instruction) = self
+            .instructions
+            .iter()
+            .enumerate()
+            .find(|(_, instruction)| instruction.applies(registers))?;
+        for &(reg, amount) in &instruction.decrements {
+            registers.set_register(reg, registers.register(reg) - amount);
+        }
+        for &(reg, amount) in &instruction.increments {
+            registers.set_register(reg, registers.register(reg) + amount);
+        }
+        Some(i)
+    }
+
+    /// Runs the machine until it halts, returning the final registers: this
+    /// will never terminate if the machine doesn't.
+    pub fn exec_to_completion(&self, mut registers: PrimeBasis) -> PrimeBasis {
+        while self.step(&mut registers).is_some() {}
+        registers
+    }
+}
+
+impl Program<PrimeBasis> {
+    /// Translates this program into a `RegisterMachine`. Each fraction becomes
+    /// an instruction that decrements the registers of its denominator and
+    /// increments the registers of its numerator, after cancelling registers
+    /// that appear on both sides: `6/3` only needs `r0` to be incremented, and
+    /// always applies.
+    pub fn to_register_machine(&self) -> RegisterMachine {
+        let instructions = self
+            .fractions()
+            .iter()
+            .map(|frac| {
+                let (num, denom) = (frac.num(), frac.denom());
+                let len = num.exps.len().max(denom.exps.len());
+                let mut instruction = RegisterInstruction {
+                    decrements: vec![],
+                    increments: vec![],
+                };
+                for reg in 0..len {
+                    let (inc, dec) = (num.register(reg), denom.register(reg));
+                    if dec > inc {
+                        instruction.decrements.push((reg, dec - inc));
+                    } else if inc > dec {
+                        instruction.increments.push((reg, inc - dec));
+                    }
+                }
+                instruction
+            })
+            .collect();
+        RegisterMachine { instructions }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::StepEvaluator;
+
+    fn mult_program() -> Program<PrimeBasis> {
+        Program::from_u64_fractions(&[(455, 33), (11, 13), (1, 11), (3, 7), (11, 2), (1, 3)])
+            .unwrap()
+    }
+
+    #[test]
+    fn test_translation() {
+        let machine = mult_program().to_register_machine();
+        assert_eq!(machine.instructions().len(), 6);
+        // 455/33 = (5 ✕ 7 ✕ 13) / (3 ✕ 11)
+        assert_eq!(
+            machine.instructions()[0],
+            RegisterInstruction {
+                decrements: vec![(1, 1), (4, 1)],
+                increments: vec![(2, 1), (3, 1), (5, 1)],
+            }
+        );
+
+        // common registers cancel
+        let prog = Program::from_u64_fractions(&[(6, 3), (4, 18)]).unwrap();
+        let machine = prog.to_register_machine();
+        assert_eq!(
+            machine.instructions(),
+            &[
+                RegisterInstruction {
+                    decrements: vec![],
+                    increments: vec![(0, 1)],
+                },
+                RegisterInstruction {
+                    decrements: vec![(1, 2)],
+                    increments: vec![(0, 1)],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_matches_program() {
+        let prog = mult_program();
+        let machine = prog.to_register_machine();
+        for a in 0..5 {
+            for b in 0..5 {
+                let input = PrimeBasis::from(vec![a, b]);
+                let mut registers = input.clone();
+                let mut stepper = prog.clone().step_evaluator(input.clone());
+                while let Some(i) = machine.step(&mut registers) {
+                    assert_eq!(
+                        StepEvaluator::step(&mut stepper),
+                        Some((i, registers.clone()))
+                    );
+                }
+                assert_eq!(StepEvaluator::step(&mut stepper), None);
+                assert_eq!(
+                    machine.exec_to_completion(input.clone()),
+                    prog.exec_to_completion_ref(input)
+                );
+            }
+        }
+    }
+}